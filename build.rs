@@ -0,0 +1,47 @@
+use std::env;
+use std::process::Command;
+use std::str;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let compiler = match rustc_minor_version() {
+        Some(compiler) => compiler,
+        None => return,
+    };
+
+    if compiler >= 80 {
+        println!("cargo:rustc-check-cfg=cfg(no_cfg_panic)");
+        println!("cargo:rustc-check-cfg=cfg(no_is_terminal)");
+        println!("cargo:rustc-check-cfg=cfg(no_scoped_threads)");
+    }
+
+    if compiler < 60 {
+        // cfg(panic) stabilized in Rust 1.60
+        // https://blog.rust-lang.org/2022/04/07/Rust-1.60.0.html
+        println!("cargo:rustc-cfg=no_cfg_panic");
+    }
+
+    if compiler < 63 {
+        // std::thread::scope stabilized in Rust 1.63
+        // https://blog.rust-lang.org/2022/08/11/Rust-1.63.0.html#scoped-threads
+        println!("cargo:rustc-cfg=no_scoped_threads");
+    }
+
+    if compiler < 70 {
+        // std::io::IsTerminal stabilized in Rust 1.70
+        // https://blog.rust-lang.org/2023/06/01/Rust-1.70.0.html#isterminal
+        println!("cargo:rustc-cfg=no_is_terminal");
+    }
+}
+
+fn rustc_minor_version() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = str::from_utf8(&output.stdout).ok()?;
+    let mut pieces = version.split('.');
+    if pieces.next() != Some("rustc 1") {
+        return None;
+    }
+    pieces.next()?.parse().ok()
+}
@@ -0,0 +1,42 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Error, Expr, ItemFn};
+
+#[proc_macro_attribute]
+pub fn task(args: TokenStream, input: TokenStream) -> TokenStream {
+    if args.is_empty() {
+        let msg = "expected the Sequencer to begin tasks from, as in #[oqueue::task(SEQUENCER)]";
+        return Error::new(Span::call_site(), msg)
+            .into_compile_error()
+            .into();
+    }
+    let sequencer = parse_macro_input!(args as Expr);
+    let function = parse_macro_input!(input as ItemFn);
+    expand(&sequencer, function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(sequencer: &Expr, function: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(asyncness) = function.sig.asyncness {
+        let msg = "#[oqueue::task] is not supported on async fn, because the task would stay current on whichever thread polls it";
+        return Err(Error::new_spanned(asyncness, msg));
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+    let stmts = &block.stmts;
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            let __oqueue_task = ::oqueue::__enter_task(&#sequencer);
+            #(#stmts)*
+        }
+    })
+}
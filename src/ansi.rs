@@ -0,0 +1,312 @@
+use std::convert::TryInto;
+use termcolor::{Color, ColorSpec};
+
+const ESC: u8 = b'\x1b';
+
+/// Removes SGR escape sequences, the ones that set color and text attributes,
+/// from a stream of bytes, as well as OSC sequences such as hyperlinks.
+/// Sequences that are split across multiple calls are recognized too.
+pub(crate) struct Stripper {
+    /// Beginning of a potential escape sequence, withheld from the output
+    /// until it is known whether the sequence is SGR or OSC.
+    held: Vec<u8>,
+}
+
+/// Longest OSC sequence withheld while waiting for its end, beyond which it
+/// is assumed not to be one.
+const MAX_OSC: usize = 4096;
+
+impl Stripper {
+    pub(crate) fn new() -> Self {
+        Stripper { held: Vec::new() }
+    }
+
+    pub(crate) fn strip(&mut self, mut input: &[u8], out: &mut Vec<u8>) {
+        out.reserve(input.len());
+        loop {
+            // Outside of escape sequences, everything up to the next ESC is
+            // copied in one go.
+            if self.held.is_empty() {
+                let esc = find_esc(input).unwrap_or(input.len());
+                out.extend_from_slice(&input[..esc]);
+                input = &input[esc..];
+            }
+            match input.split_first() {
+                Some((&byte, rest)) => {
+                    self.step(byte, out);
+                    input = rest;
+                }
+                None => return,
+            }
+        }
+    }
+
+    fn step(&mut self, byte: u8, out: &mut Vec<u8>) {
+        match self.held.len() {
+            0 => self.ground(byte, out),
+            1 if byte == b'[' || byte == b']' => self.held.push(byte),
+            1 => {
+                out.append(&mut self.held);
+                self.ground(byte, out);
+            }
+            _ if self.held[1] == b']' => self.osc(byte, out),
+            _ => match byte {
+                // Parameter and intermediate bytes.
+                0x20..=0x3F => self.held.push(byte),
+                b'm' => self.held.clear(),
+                // Final byte of some other control sequence.
+                0x40..=0x7E => {
+                    out.append(&mut self.held);
+                    out.push(byte);
+                }
+                _ => {
+                    out.append(&mut self.held);
+                    self.ground(byte, out);
+                }
+            },
+        }
+    }
+
+    /// Continues an OSC sequence, which ends with BEL or with ESC \.
+    fn osc(&mut self, byte: u8, out: &mut Vec<u8>) {
+        let escaped = self.held.last() == Some(&ESC);
+        if byte == b'\x07' || (escaped && byte == b'\\') {
+            self.held.clear();
+        } else if escaped {
+            // Some other escape sequence begins in the middle.
+            self.held.pop();
+            out.append(&mut self.held);
+            self.ground(ESC, out);
+            self.strip(&[byte], out);
+        } else if self.held.len() < MAX_OSC {
+            self.held.push(byte);
+        } else {
+            out.append(&mut self.held);
+            out.push(byte);
+        }
+    }
+
+    fn ground(&mut self, byte: u8, out: &mut Vec<u8>) {
+        if byte == ESC {
+            self.held.push(byte);
+        } else {
+            out.push(byte);
+        }
+    }
+}
+
+/// Position of the first ESC in `bytes`, looking at a word's worth of bytes at
+/// a time.
+fn find_esc(bytes: &[u8]) -> Option<usize> {
+    const LO: u64 = u64::from_ne_bytes([0x01; 8]);
+    const HI: u64 = u64::from_ne_bytes([0x80; 8]);
+    const PATTERN: u64 = u64::from_ne_bytes([ESC; 8]);
+
+    let mut offset = 0;
+    for chunk in bytes.chunks_exact(8) {
+        // Zero in each byte that is ESC, which sets the high bit of that byte
+        // after the subtraction.
+        let word = u64::from_ne_bytes(chunk.try_into().unwrap()) ^ PATTERN;
+        if word.wrapping_sub(LO) & !word & HI != 0 {
+            break;
+        }
+        offset += 8;
+    }
+    let esc = bytes[offset..].iter().position(|&b| b == ESC)?;
+    Some(offset + esc)
+}
+
+/// Removes escape sequences other than SGR from a stream of bytes, together
+/// with control characters other than tab, newline and carriage return, so
+/// that output from an untrusted source cannot move the cursor, clear the
+/// screen or change the terminal's title. Sequences that are split across
+/// multiple calls are recognized too.
+pub(crate) struct Sanitizer {
+    state: State,
+    /// CSI sequence in progress, withheld until it is known whether it is SGR.
+    held: Vec<u8>,
+}
+
+enum State {
+    Ground,
+    /// After ESC.
+    Escape,
+    /// After ESC followed by intermediate bytes.
+    Intermediate,
+    /// After ESC [.
+    Csi,
+    /// In a string such as the payload of an OSC sequence, which ends with
+    /// BEL or with ESC \.
+    String,
+    /// After ESC in a string.
+    StringEscape,
+}
+
+impl Sanitizer {
+    pub(crate) fn new() -> Self {
+        Sanitizer {
+            state: State::Ground,
+            held: Vec::new(),
+        }
+    }
+
+    pub(crate) fn sanitize(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        for &byte in input {
+            self.state = match self.state {
+                State::Ground => ground(byte, out),
+                State::Escape => match byte {
+                    b'[' => {
+                        self.held.clear();
+                        self.held.extend_from_slice(b"\x1b[");
+                        State::Csi
+                    }
+                    b']' | b'P' | b'X' | b'^' | b'_' => State::String,
+                    0x20..=0x2F => State::Intermediate,
+                    ESC => State::Escape,
+                    // Final byte of a two byte sequence.
+                    0x30..=0x7E => State::Ground,
+                    _ => ground(byte, out),
+                },
+                State::Intermediate => match byte {
+                    0x20..=0x2F => State::Intermediate,
+                    0x30..=0x7E => State::Ground,
+                    _ => ground(byte, out),
+                },
+                State::Csi => match byte {
+                    // Parameter and intermediate bytes.
+                    0x20..=0x3F => {
+                        if self.held.len() < MAX_CSI {
+                            self.held.push(byte);
+                        }
+                        State::Csi
+                    }
+                    b'm' if self.is_sgr() => {
+                        out.append(&mut self.held);
+                        out.push(byte);
+                        State::Ground
+                    }
+                    // Final byte of some other control sequence.
+                    0x40..=0x7E => State::Ground,
+                    _ => ground(byte, out),
+                },
+                State::String => match byte {
+                    b'\x07' => State::Ground,
+                    ESC => State::StringEscape,
+                    _ => State::String,
+                },
+                State::StringEscape => match byte {
+                    b'\\' => State::Ground,
+                    ESC => State::StringEscape,
+                    _ => State::String,
+                },
+            };
+        }
+    }
+
+    /// Whether the CSI sequence withheld is SGR, if it ends with `m`.
+    fn is_sgr(&self) -> bool {
+        self.held.len() < MAX_CSI && self.held[2..].iter().all(|b| b"0123456789;:".contains(b))
+    }
+}
+
+/// Lets through a byte outside of any escape sequence, unless it is a
+/// control character other than tab, newline and carriage return.
+fn ground(byte: u8, out: &mut Vec<u8>) -> State {
+    match byte {
+        ESC => State::Escape,
+        b'\t' | b'\n' | b'\r' => {
+            out.push(byte);
+            State::Ground
+        }
+        0x00..=0x1F | 0x7F => State::Ground,
+        _ => {
+            out.push(byte);
+            State::Ground
+        }
+    }
+}
+
+/// Length of CSI sequence withheld while waiting for its end at which it is
+/// assumed not to be SGR.
+const MAX_CSI: usize = 64;
+
+/// Interprets the SGR escape sequences in `text` as a termcolor `ColorSpec`.
+/// Everything else in the text is ignored.
+#[cfg(any(feature = "owo-colors", feature = "yansi"))]
+pub(crate) fn parse_sgr(text: &str) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+
+    let sequences = text
+        .split('\x1b')
+        .filter_map(|s| s.strip_prefix('[')?.strip_suffix('m'));
+
+    for sequence in sequences {
+        apply_sgr(&mut spec, sequence);
+    }
+
+    spec
+}
+
+/// Updates `spec` according to the parameters of one SGR escape sequence,
+/// the part between `ESC [` and `m`.
+pub(crate) fn apply_sgr(spec: &mut ColorSpec, sequence: &str) {
+    let mut params = sequence.split(';').map(|param| {
+        if param.is_empty() {
+            Some(0)
+        } else {
+            param.parse::<u8>().ok()
+        }
+    });
+    while let Some(param) = params.next() {
+        let param = match param {
+            Some(param) => param,
+            None => continue,
+        };
+        match param {
+            0 => spec.clear(),
+            1 => drop(spec.set_bold(true)),
+            2 => drop(spec.set_dimmed(true)),
+            3 => drop(spec.set_italic(true)),
+            4 => drop(spec.set_underline(true)),
+            9 => drop(spec.set_strikethrough(true)),
+            22 => drop(spec.set_bold(false).set_dimmed(false)),
+            23 => drop(spec.set_italic(false)),
+            24 => drop(spec.set_underline(false)),
+            29 => drop(spec.set_strikethrough(false)),
+            30..=37 => drop(spec.set_fg(Some(basic(param - 30)))),
+            38 => drop(spec.set_fg(extended(&mut params))),
+            39 => drop(spec.set_fg(None)),
+            40..=47 => drop(spec.set_bg(Some(basic(param - 40)))),
+            48 => drop(spec.set_bg(extended(&mut params))),
+            49 => drop(spec.set_bg(None)),
+            90..=97 => drop(spec.set_fg(Some(basic(param - 90))).set_intense(true)),
+            100..=107 => drop(spec.set_bg(Some(basic(param - 100))).set_intense(true)),
+            _ => {}
+        }
+    }
+}
+
+fn basic(n: u8) -> Color {
+    match n {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        _ => Color::White,
+    }
+}
+
+fn extended(params: &mut impl Iterator<Item = Option<u8>>) -> Option<Color> {
+    match params.next()?? {
+        5 => Some(Color::Ansi256(params.next()??)),
+        2 => Some(Color::Rgb(
+            params.next()??,
+            params.next()??,
+            params.next()??,
+        )),
+        _ => None,
+    }
+}
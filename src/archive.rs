@@ -0,0 +1,95 @@
+use crate::ansi::Stripper;
+use crate::json::Str;
+use crate::junit::Case;
+use std::fs::{self, File};
+use std::io::{BufWriter, Result, Write};
+use std::path::PathBuf;
+
+/// Directory receiving a log file of each task's output as soon as the task
+/// finishes, and a manifest of all of them once the run is over.
+pub(crate) struct Archive {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    case: Case,
+    file: String,
+}
+
+/// Longest part of a log file's name taken from the task's title.
+const MAX_SLUG: usize = 64;
+
+impl Archive {
+    pub(crate) fn new(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Archive {
+            dir,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Writes the log file of a finished task.
+    pub(crate) fn record(&mut self, mut case: Case, titled: bool) -> Result<()> {
+        let mut file = format!("task-{}", case.index);
+        if titled {
+            let slug = slug(&case.name);
+            if !slug.is_empty() {
+                file.push('-');
+                file.push_str(&slug);
+            }
+        }
+        file.push_str(".log");
+
+        let mut plain = Vec::new();
+        Stripper::new().strip(&case.output, &mut plain);
+        case.output = Vec::new();
+        let written = fs::write(self.dir.join(&file), plain);
+        self.entries.push(Entry { case, file });
+        written
+    }
+
+    /// Writes `manifest.json`, listing the tasks in order of index.
+    pub(crate) fn write_manifest(&mut self) -> Result<()> {
+        self.entries.sort_by_key(|entry| entry.case.index);
+        let mut w = BufWriter::new(File::create(self.dir.join("manifest.json"))?);
+        writeln!(w, "[")?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let case = &entry.case;
+            let comma = if i + 1 < self.entries.len() { "," } else { "" };
+            writeln!(
+                w,
+                r#"  {{"index":{},"name":{},"file":{},"failed":{},"cancelled":{},"elapsed":{:.6}}}{}"#,
+                case.index,
+                Str(&case.name),
+                Str(&entry.file),
+                case.failed,
+                case.skipped,
+                case.elapsed.as_secs_f64(),
+                comma,
+            )?;
+        }
+        writeln!(w, "]")?;
+        w.flush()
+    }
+}
+
+/// Part of a file name standing for the given title, made of letters, digits,
+/// dashes, dots and underscores.
+fn slug(title: &str) -> String {
+    let mut slug = String::new();
+    for ch in title.chars() {
+        if ch.is_alphanumeric() || ch == '.' || ch == '_' {
+            slug.push(ch);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG {
+            break;
+        }
+    }
+    while slug.ends_with(|ch| ch == '-' || ch == '.') {
+        slug.pop();
+    }
+    slug
+}
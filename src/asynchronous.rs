@@ -0,0 +1,284 @@
+//! Sequencer for tasks running on a tokio runtime.
+//!
+//! This has the same ordering guarantee as [`oqueue::Sequencer`], but output is
+//! written by a tokio task through an [`AsyncWrite`] rather than by whichever
+//! thread happens to finish the head task, so no runtime thread is ever
+//! blocked on a slow destination. Tasks are `Send` and can be held across
+//! `.await` points.
+//!
+//! [`oqueue::Sequencer`]: crate::Sequencer
+//!
+//! ```
+//! use oqueue::asynchronous::Sequencer;
+//! use std::sync::Arc;
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+//! runtime.block_on(async {
+//!     let oqueue = Arc::new(Sequencer::stdout());
+//!
+//!     let mut handles = Vec::new();
+//!     for _ in 0..10 {
+//!         let task = oqueue.begin();
+//!         handles.push(tokio::spawn(async move {
+//!             tokio::task::yield_now().await;
+//!             writeln!(task, "hello from task #{}", task.index);
+//!         }));
+//!     }
+//!     for handle in handles {
+//!         handle.await.unwrap();
+//!     }
+//!
+//!     Arc::try_unwrap(oqueue).ok().unwrap().finish().await
+//! })?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::sequencer::{Handle, Sequencer as SyncSequencer};
+use crate::sink::Sink;
+use std::fmt::{self, Debug};
+use std::io::{self, Result, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use termcolor::Buffer;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// Synchronization mechanism for performing non-interleaved output from
+/// concurrent async tasks.
+///
+/// Output is written without color.
+pub struct Sequencer {
+    sync: SyncSequencer,
+    sender: mpsc::UnboundedSender<Message>,
+    writer: JoinHandle<Result<()>>,
+}
+
+/// Unit of work arranged by an async Sequencer.
+///
+/// Use the standard library `write!` or `writeln!` macros, or
+/// [`AsyncWriteExt`] methods, to write the output of a task. Writing only
+/// copies into memory and never waits.
+#[readonly::make]
+#[derive(Clone)]
+pub struct Task {
+    handle: Arc<Handle>,
+
+    /// Index of the current task, the same as [`crate::Task::index`].
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub index: usize,
+}
+
+enum Message {
+    Print(Buffer),
+    /// Flush, then report back the outcome if anyone is waiting for it.
+    Flush(Option<oneshot::Sender<Result<()>>>),
+}
+
+/// Sink handing everything to the writer task.
+struct Channel {
+    sender: mpsc::UnboundedSender<Message>,
+}
+
+impl Sequencer {
+    /// Makes a sequencer whose output goes to the given async writer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from outside of a tokio runtime.
+    pub fn new<W>(writer: W) -> Self
+    where
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write(writer, receiver));
+        let channel = Channel {
+            sender: sender.clone(),
+        };
+        Sequencer {
+            sync: SyncSequencer::new(Box::new(channel)),
+            sender,
+            writer,
+        }
+    }
+
+    /// Makes a sequencer whose output goes to stdout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from outside of a tokio runtime.
+    pub fn stdout() -> Self {
+        Self::new(tokio::io::stdout())
+    }
+
+    /// Makes a sequencer whose output goes to stderr.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from outside of a tokio runtime.
+    pub fn stderr() -> Self {
+        Self::new(tokio::io::stderr())
+    }
+
+    /// Begins the next available task.
+    ///
+    /// This call does not block.
+    pub fn begin(&self) -> Task {
+        let handle = self.sync.begin_handle();
+        Task {
+            index: handle.index(),
+            handle: Arc::new(handle),
+        }
+    }
+
+    /// Waits until all output printed so far has been written and the writer
+    /// flushed.
+    ///
+    /// Output of tasks that are still waiting for earlier tasks to finish is
+    /// not printed yet, so is not waited for.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered by the writer since the last
+    /// flush.
+    pub async fn flush(&self) -> Result<()> {
+        let (ack, done) = oneshot::channel();
+        if self.sender.send(Message::Flush(Some(ack))).is_err() {
+            return Err(writer_exited());
+        }
+        done.await.unwrap_or_else(|_| Err(writer_exited()))
+    }
+
+    /// Waits until the output of every task has been written, then stops the
+    /// writer.
+    ///
+    /// Tasks that are still running at this point get printed once they
+    /// finish. Dropping the Sequencer without calling this leaves the output
+    /// to be written in the background, which can be cut off if the runtime
+    /// shuts down first.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered by the writer since the last
+    /// flush.
+    pub async fn finish(self) -> Result<()> {
+        let Sequencer {
+            sync,
+            sender,
+            writer,
+        } = self;
+        drop(sync);
+        drop(sender);
+        match writer.await {
+            Ok(result) => result,
+            Err(error) => Err(io::Error::new(io::ErrorKind::Other, error)),
+        }
+    }
+}
+
+async fn write<W>(mut writer: W, mut receiver: mpsc::UnboundedReceiver<Message>) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut result = Ok(());
+    while let Some(message) = receiver.recv().await {
+        match message {
+            Message::Print(buffer) => {
+                if result.is_ok() {
+                    result = writer.write_all(buffer.as_slice()).await;
+                }
+            }
+            Message::Flush(ack) => {
+                let flushed = match result {
+                    Ok(()) => writer.flush().await,
+                    Err(error) => Err(error),
+                };
+                result = Ok(());
+                if let Some(ack) = ack {
+                    let _ = ack.send(flushed);
+                }
+            }
+        }
+    }
+    result?;
+    writer.flush().await
+}
+
+fn writer_exited() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "output task exited")
+}
+
+impl Sink for Channel {
+    fn buffer(&self) -> Buffer {
+        Buffer::no_color()
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        self.sender
+            .send(Message::Print(buffer.clone()))
+            .map_err(|_| writer_exited())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sender
+            .send(Message::Flush(None))
+            .map_err(|_| writer_exited())
+    }
+}
+
+impl Debug for Task {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_tuple("Task").field(&self.index).finish()
+    }
+}
+
+impl Task {
+    #[doc(hidden)]
+    pub fn write_fmt(&self, args: fmt::Arguments) {
+        let _ = self.handle.apply(|w| w.write_fmt(args));
+    }
+}
+
+impl Write for Task {
+    fn write(&mut self, b: &[u8]) -> Result<usize> {
+        self.handle.apply(|w| w.write(b))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.handle.apply(|w| w.write_all(buf))
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> Result<()> {
+        self.handle.apply(|w| w.write_fmt(args))
+    }
+}
+
+impl AsyncWrite for Task {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        Poll::Ready(self.handle.apply(|w| w.write(buf)))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+struct _Test
+where
+    Sequencer: Send + Sync,
+    Task: Send + Sync;
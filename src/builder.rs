@@ -0,0 +1,466 @@
+use super::Sequencer;
+use crate::folding::Folding;
+use crate::info::TaskInfo;
+use crate::messages::Messages;
+use crate::palette::ColorDepth;
+#[cfg(feature = "indicatif")]
+use crate::sink::Progress;
+use crate::sink::{Queue, Sink, Stream, Tee, Writer};
+use crate::theme::Theme;
+use crate::timestamp::Timestamps;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use termcolor::{Color, ColorChoice};
+
+/// Configuration for a Sequencer, made by [`Sequencer::builder`].
+///
+/// Each of these options is also available as a method on `Sequencer`; the
+/// builder is for composing them in one place, for example from parsed
+/// command line flags.
+///
+/// ```
+/// use oqueue::{ColorChoice, Sequencer};
+///
+/// # let (color, ci) = (ColorChoice::Auto, false);
+/// let mut builder = Sequencer::builder().stderr().color(color).max_pending(64);
+/// if ci {
+///     builder = builder.batch();
+/// }
+/// let oqueue = builder.build();
+/// ```
+#[must_use = "call .build() to make the Sequencer"]
+pub struct SequencerBuilder {
+    destination: Destination,
+    color: ColorChoice,
+    tee: Vec<Box<dyn Write + Send>>,
+    #[cfg(feature = "indicatif")]
+    progress: Option<indicatif::MultiProgress>,
+    printer: Printer,
+    /// Options applied to the Sequencer once it exists, in order.
+    options: Vec<Box<dyn FnOnce(Sequencer) -> Sequencer>>,
+}
+
+/// Which thread does the printing.
+enum Printer {
+    /// Whichever thread has output ready to print.
+    Task,
+    /// A background thread, fed through a queue of the given capacity.
+    Thread(Option<usize>),
+}
+
+enum Destination {
+    Stdout,
+    Stderr,
+    Writer(Box<dyn Write + Send>),
+}
+
+impl Sequencer {
+    /// Begins configuring a Sequencer. By default its output goes to stdout
+    /// with color detected automatically.
+    pub fn builder() -> SequencerBuilder {
+        SequencerBuilder {
+            destination: Destination::Stdout,
+            color: ColorChoice::Auto,
+            tee: Vec::new(),
+            #[cfg(feature = "indicatif")]
+            progress: None,
+            printer: Printer::Task,
+            options: Vec::new(),
+        }
+    }
+}
+
+impl SequencerBuilder {
+    /// Sends output to stdout. This is the default.
+    pub fn stdout(mut self) -> Self {
+        self.destination = Destination::Stdout;
+        self
+    }
+
+    /// Sends output to stderr.
+    pub fn stderr(mut self) -> Self {
+        self.destination = Destination::Stderr;
+        self
+    }
+
+    /// Sends output to the given writer. Refer to
+    /// [`Sequencer::with_writer`].
+    pub fn writer<W>(mut self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.destination = Destination::Writer(Box::new(writer));
+        self
+    }
+
+    /// Controls whether output is colored. Refer to
+    /// [`Sequencer::stdout_with_color`].
+    ///
+    /// For a [writer][SequencerBuilder::writer], `Always` and `AlwaysAnsi`
+    /// produce ANSI color codes while `Auto` and `Never` produce no color.
+    pub fn color(mut self, choice: ColorChoice) -> Self {
+        self.color = choice;
+        self
+    }
+
+    /// Refer to [`Sequencer::tee`]. This may be called more than once to
+    /// write more copies.
+    pub fn tee<W>(mut self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.tee.push(Box::new(writer));
+        self
+    }
+
+    /// Refer to [`Sequencer::asciicast`].
+    pub fn asciicast<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.then(move |sequencer| sequencer.asciicast(writer))
+    }
+
+    /// Refer to [`Sequencer::multi_progress`]. Unlike there, it does not
+    /// matter in which order this and a writer thread are configured.
+    #[cfg(feature = "indicatif")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "indicatif")))]
+    pub fn multi_progress(mut self, multi: &indicatif::MultiProgress) -> Self {
+        self.progress = Some(multi.clone());
+        self
+    }
+
+    /// Refer to [`Sequencer::nonblocking`].
+    pub fn nonblocking(mut self, capacity: usize) -> Self {
+        self.printer = Printer::Thread(Some(capacity));
+        self
+    }
+
+    /// Refer to [`Sequencer::writer_thread`].
+    pub fn writer_thread(mut self) -> Self {
+        self.printer = Printer::Thread(None);
+        self
+    }
+
+    /// Refer to [`Sequencer::batch`].
+    pub fn batch(self) -> Self {
+        self.then(Sequencer::batch)
+    }
+
+    /// Refer to [`Sequencer::whole_tasks`]. This overrides the default of
+    /// printing whole tasks when stdout or stderr is not a terminal.
+    pub fn whole_tasks(self, enabled: bool) -> Self {
+        self.then(move |sequencer| sequencer.whole_tasks(enabled))
+    }
+
+    /// Refer to [`Sequencer::watchdog`].
+    pub fn watchdog(self, interval: Duration) -> Self {
+        self.then(move |sequencer| sequencer.watchdog(interval))
+    }
+
+    /// Refer to [`Sequencer::keepalive`].
+    pub fn keepalive(self, interval: Duration) -> Self {
+        self.then(move |sequencer| sequencer.keepalive(interval))
+    }
+
+    /// Refer to [`Sequencer::timeout`].
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.then(move |sequencer| sequencer.timeout(timeout))
+    }
+
+    /// Refer to [`Sequencer::fail_fast`].
+    pub fn fail_fast(self, failures: usize) -> Self {
+        self.then(move |sequencer| sequencer.fail_fast(failures))
+    }
+
+    /// Refer to [`Sequencer::capture_panics`].
+    pub fn capture_panics(self) -> Self {
+        self.then(Sequencer::capture_panics)
+    }
+
+    /// Refer to [`Sequencer::flush_on_interrupt`].
+    #[cfg(feature = "interrupt")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "interrupt")))]
+    pub fn flush_on_interrupt(self) -> Self {
+        self.then(Sequencer::flush_on_interrupt)
+    }
+
+    /// Refer to [`Sequencer::flush_on_exit`].
+    #[cfg(feature = "exit")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "exit")))]
+    pub fn flush_on_exit(self) -> Self {
+        self.then(Sequencer::flush_on_exit)
+    }
+
+    /// Refer to [`Sequencer::quiet_unless_failed`].
+    pub fn quiet_unless_failed(self) -> Self {
+        self.then(Sequencer::quiet_unless_failed)
+    }
+
+    /// Refer to [`Sequencer::tap`].
+    pub fn tap(self) -> Self {
+        self.then(Sequencer::tap)
+    }
+
+    /// Refer to [`Sequencer::junit_report`].
+    pub fn junit_report<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.then(move |sequencer| sequencer.junit_report(writer))
+    }
+
+    /// Refer to [`Sequencer::html_report`].
+    pub fn html_report<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.then(move |sequencer| sequencer.html_report(writer))
+    }
+
+    /// Refer to [`Sequencer::archive`].
+    pub fn archive(self, dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_owned();
+        self.then(move |sequencer| sequencer.archive(dir))
+    }
+
+    /// Refer to [`Sequencer::event_log`].
+    pub fn event_log<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.then(move |sequencer| sequencer.event_log(writer))
+    }
+
+    /// Refer to [`Sequencer::json`].
+    pub fn json(self) -> Self {
+        self.then(Sequencer::json)
+    }
+
+    /// Refer to [`Sequencer::print_summary`].
+    pub fn print_summary(self) -> Self {
+        self.then(Sequencer::print_summary)
+    }
+
+    /// Refer to [`Sequencer::print_peak_memory`].
+    pub fn print_peak_memory(self) -> Self {
+        self.then(Sequencer::print_peak_memory)
+    }
+
+    /// Refer to [`Sequencer::export_metrics`].
+    #[cfg(feature = "metrics")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+    pub fn export_metrics(self) -> Self {
+        self.then(Sequencer::export_metrics)
+    }
+
+    /// Refer to [`Sequencer::export_spans`].
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "opentelemetry")))]
+    pub fn export_spans(self) -> Self {
+        self.then(Sequencer::export_spans)
+    }
+
+    /// Refer to [`Sequencer::completion_order`].
+    pub fn completion_order(self) -> Self {
+        self.then(Sequencer::completion_order)
+    }
+
+    /// Refer to [`Sequencer::interleaved`].
+    pub fn interleaved(self) -> Self {
+        self.then(Sequencer::interleaved)
+    }
+
+    /// Refer to [`Sequencer::folding`]. For example `.folding(None)` turns
+    /// off folding even when running in CI.
+    pub fn folding(self, folding: Option<Folding>) -> Self {
+        self.then(move |sequencer| sequencer.folding(folding))
+    }
+
+    /// Refer to [`Sequencer::prefix_colors`].
+    pub fn prefix_colors(self, colors: impl IntoIterator<Item = Color>) -> Self {
+        let colors: Vec<Color> = colors.into_iter().collect();
+        self.then(move |sequencer| sequencer.prefix_colors(colors))
+    }
+
+    /// Refer to [`Sequencer::status_footer`].
+    pub fn status_footer(self) -> Self {
+        self.then(Sequencer::status_footer)
+    }
+
+    /// Refer to [`Sequencer::title_footers`].
+    pub fn title_footers(self) -> Self {
+        self.then(Sequencer::title_footers)
+    }
+
+    /// Refer to [`Sequencer::print_durations`].
+    pub fn print_durations(self) -> Self {
+        self.then(Sequencer::print_durations)
+    }
+
+    /// Refer to [`Sequencer::dedup_lines`].
+    pub fn dedup_lines(self) -> Self {
+        self.then(Sequencer::dedup_lines)
+    }
+
+    /// Refer to [`Sequencer::sanitize`].
+    pub fn sanitize(self) -> Self {
+        self.then(Sequencer::sanitize)
+    }
+
+    /// Refer to [`Sequencer::collapse_progress`].
+    pub fn collapse_progress(self) -> Self {
+        self.then(Sequencer::collapse_progress)
+    }
+
+    /// Refer to [`Sequencer::max_lines`].
+    pub fn max_lines(self, lines: usize) -> Self {
+        self.then(move |sequencer| sequencer.max_lines(lines))
+    }
+
+    /// Refer to [`Sequencer::max_bytes_per_second`].
+    pub fn max_bytes_per_second(self, bytes: usize) -> Self {
+        self.then(move |sequencer| sequencer.max_bytes_per_second(bytes))
+    }
+
+    /// Refer to [`Sequencer::timestamps`].
+    pub fn timestamps(self, format: Timestamps) -> Self {
+        self.then(move |sequencer| sequencer.timestamps(format))
+    }
+
+    /// Refer to [`Sequencer::color_depth`].
+    pub fn color_depth(self, depth: ColorDepth) -> Self {
+        self.then(move |sequencer| sequencer.color_depth(depth))
+    }
+
+    /// Refer to [`Sequencer::max_pending`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tasks` is 0.
+    pub fn max_pending(self, tasks: usize) -> Self {
+        assert!(tasks > 0, "max_pending must allow at least one task");
+        self.then(move |sequencer| sequencer.max_pending(tasks))
+    }
+
+    /// Refer to [`Sequencer::spill_to_disk`].
+    pub fn spill_to_disk(self, bytes: usize) -> Self {
+        self.then(move |sequencer| sequencer.spill_to_disk(bytes))
+    }
+
+    /// Refer to [`Sequencer::elide`].
+    pub fn elide(self, head: usize, tail: usize) -> Self {
+        self.then(move |sequencer| sequencer.elide(head, tail))
+    }
+
+    /// Refer to [`Sequencer::keep_tail`].
+    pub fn keep_tail(self, bytes: usize) -> Self {
+        self.then(move |sequencer| sequencer.keep_tail(bytes))
+    }
+
+    /// Refer to [`Sequencer::with_start_index`].
+    pub fn start_index(self, index: usize) -> Self {
+        self.then(move |sequencer| sequencer.with_start_index(index))
+    }
+
+    /// Refer to [`Sequencer::with_len`].
+    pub fn len(self, len: usize) -> Self {
+        self.then(move |sequencer| sequencer.with_len(len))
+    }
+
+    /// Refer to [`Sequencer::messages`].
+    pub fn messages(self, messages: impl Messages + 'static) -> Self {
+        self.then(move |sequencer| sequencer.messages(messages))
+    }
+
+    /// Refer to [`Sequencer::theme`].
+    pub fn theme(self, theme: Theme) -> Self {
+        self.then(move |sequencer| sequencer.theme(theme))
+    }
+
+    /// Refer to [`Sequencer::unicode`].
+    pub fn unicode(self, enabled: bool) -> Self {
+        self.then(move |sequencer| sequencer.unicode(enabled))
+    }
+
+    /// Refer to [`Sequencer::on_task_start`].
+    pub fn on_task_start<F>(self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.then(move |sequencer| sequencer.on_task_start(f))
+    }
+
+    /// Refer to [`Sequencer::on_task_complete`].
+    pub fn on_task_complete<F>(self, f: F) -> Self
+    where
+        F: Fn(&TaskInfo) + Send + Sync + 'static,
+    {
+        self.then(move |sequencer| sequencer.on_task_complete(f))
+    }
+
+    /// Refer to [`Sequencer::on_task_flushed`].
+    pub fn on_task_flushed<F>(self, f: F) -> Self
+    where
+        F: Fn(&TaskInfo) + Send + Sync + 'static,
+    {
+        self.then(move |sequencer| sequencer.on_task_flushed(f))
+    }
+
+    /// Refer to [`Sequencer::route`].
+    pub fn route<F>(self, other: Sequencer, predicate: F) -> Self
+    where
+        F: Fn(&TaskInfo) -> bool + Send + 'static,
+    {
+        self.then(move |sequencer| sequencer.route(other, predicate))
+    }
+
+    fn then(mut self, option: impl FnOnce(Sequencer) -> Sequencer + 'static) -> Self {
+        self.options.push(Box::new(option));
+        self
+    }
+
+    /// Makes the Sequencer.
+    pub fn build(self) -> Sequencer {
+        let stream = !matches!(self.destination, Destination::Writer(_));
+        let mut stderr = None;
+        let mut sink: Box<dyn Sink> = match self.destination {
+            Destination::Stdout => {
+                let (stdout, err) = Stream::pair(self.color);
+                stderr = Some(err);
+                Box::new(stdout)
+            }
+            Destination::Stderr => Box::new(Stream::stderr(self.color)),
+            Destination::Writer(writer) => {
+                let color = match self.color {
+                    ColorChoice::Always | ColorChoice::AlwaysAnsi => true,
+                    ColorChoice::Auto | ColorChoice::Never => false,
+                };
+                Box::new(Writer::new(writer).color(color))
+            }
+        };
+        for copy in self.tee {
+            sink = Box::new(Tee::new(sink, copy));
+        }
+        #[cfg(feature = "indicatif")]
+        if let Some(multi) = self.progress {
+            sink = Box::new(Progress::new(sink, multi));
+        }
+        if let Printer::Thread(capacity) = self.printer {
+            sink = Box::new(Queue::new(sink, capacity));
+        }
+
+        let mut sequencer = Sequencer::new(sink);
+        if let Some(stderr) = stderr {
+            sequencer = sequencer.with_err_sink(Box::new(stderr));
+        }
+        if stream {
+            sequencer = sequencer.detect_folding().detect_whole_tasks();
+        }
+        for option in self.options {
+            sequencer = option(sequencer);
+        }
+        sequencer
+    }
+}
@@ -0,0 +1,47 @@
+use crate::sync::Mutex;
+use std::io::{Result, Write};
+use std::sync::Arc;
+
+/// In-memory destination collecting the output of a Sequencer, made by
+/// [`Sequencer::capture`][crate::Sequencer::capture].
+///
+/// This is intended for tests that assert on the exact output produced by a
+/// set of workers.
+#[derive(Clone)]
+pub struct Capture {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Capture {
+    pub(crate) fn new() -> Self {
+        Capture {
+            bytes: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Everything printed by the Sequencer so far.
+    ///
+    /// Output of tasks is only printed once it is ready to be printed in
+    /// order. Call this after the Sequencer has been dropped, or after the end
+    /// of a [`Scope`][crate::Scope], to observe the output of every task.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.lock().clone()
+    }
+
+    /// Everything printed by the Sequencer so far, as a string. Any invalid
+    /// UTF-8 is replaced with U+FFFD.
+    pub fn string(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.lock()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.bytes.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
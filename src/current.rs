@@ -0,0 +1,146 @@
+use crate::panic;
+#[cfg(feature = "macros")]
+use crate::Sequencer;
+use crate::Task;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+
+thread_local! {
+    /// Tasks entered on this thread, innermost last.
+    static CURRENT: RefCell<Vec<Task>> = RefCell::new(Vec::new());
+}
+
+/// Guard that keeps a task entered as the current task of this thread,
+/// returned by [`Task::enter`].
+///
+/// The task stops being current when the guard is dropped, which must happen
+/// on the same thread.
+#[must_use = "the task stops being current immediately if the guard is not held"]
+pub struct Entered {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Task {
+    /// Make this the current task of the calling thread until the returned
+    /// guard is dropped.
+    ///
+    /// Output written using [`oqueue::print!`] and [`oqueue::println!`], or
+    /// by the [`log`] adapter, while a task is current goes to that task,
+    /// without the task having to be passed down through every function on
+    /// the way to the code doing the printing. Entering another task while
+    /// one is current makes the new one current until its guard is dropped,
+    /// after which the previous one is current again.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// fn helper() {
+    ///     oqueue::println!("written to whichever task is current");
+    /// }
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// let task = oqueue.begin();
+    /// let _entered = task.enter();
+    /// helper();
+    /// ```
+    ///
+    /// [`oqueue::print!`]: crate::print!
+    /// [`oqueue::println!`]: crate::println!
+    /// [`log`]: https://docs.rs/log
+    pub fn enter(&self) -> Entered {
+        CURRENT.with(|current| current.borrow_mut().push(self.clone()));
+        Entered {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let task = CURRENT.try_with(|current| current.borrow_mut().pop());
+        if let (Ok(Some(task)), Some(panic)) = (task, panic::take()) {
+            task.panicked(&panic);
+        }
+    }
+}
+
+/// Calls `f` with the current task of the calling thread, if any.
+pub(crate) fn with_current<T>(f: impl FnOnce(Option<&Task>) -> T) -> T {
+    CURRENT.with(|current| f(current.borrow().last()))
+}
+
+/// Whether the current task of the calling thread, if any, captures panics.
+/// Not even while the task is in the middle of being entered or left.
+pub(crate) fn captures_panics() -> bool {
+    CURRENT
+        .try_with(|current| {
+            current.try_borrow().map_or(false, |current| {
+                current.last().map_or(false, Task::captures_panics)
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Keeps the task of a `#[oqueue::task]` function current until it returns.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub struct __TaskGuard {
+    // Dropped in this order, so the task is no longer current by the time it
+    // gets finished.
+    _entered: Option<Entered>,
+    _task: Option<Task>,
+}
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub fn __enter_task(sequencer: &Sequencer) -> __TaskGuard {
+    let nested = with_current(|task| task.map_or(false, |task| sequencer.owns(task)));
+    if nested {
+        return __TaskGuard {
+            _entered: None,
+            _task: None,
+        };
+    }
+    let task = sequencer.begin();
+    __TaskGuard {
+        _entered: Some(task.enter()),
+        _task: Some(task),
+    }
+}
+
+#[doc(hidden)]
+pub fn __print(args: fmt::Arguments) {
+    with_current(|task| match task {
+        Some(task) => task.write_fmt(args),
+        None => {
+            let _ = io::stderr().write_fmt(args);
+        }
+    });
+}
+
+/// Writes to the [current task][crate::Task::enter] of the calling thread,
+/// in the same way as `std::print!`.
+///
+/// If no task is current, the text is printed to stderr directly.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::__print(::std::format_args!($($arg)*))
+    };
+}
+
+/// Writes a line to the [current task][crate::Task::enter] of the calling
+/// thread, in the same way as `std::println!`.
+///
+/// If no task is current, the line is printed to stderr directly.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::__print(::std::format_args!("{}\n", ::std::format_args!($($arg)*)))
+    };
+}
@@ -0,0 +1,60 @@
+use std::fmt::{self, Display};
+use std::io::{BufWriter, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Destination of the newline-delimited JSON events describing a run.
+pub(crate) struct Events {
+    writer: BufWriter<Box<dyn Write + Send>>,
+}
+
+impl Events {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Self {
+        Events {
+            writer: BufWriter::new(writer),
+        }
+    }
+
+    pub(crate) fn started(&mut self, index: usize) {
+        let _ = writeln!(
+            self.writer,
+            r#"{{"event":"started","index":{},"time":{}}}"#,
+            index, Time,
+        );
+    }
+
+    pub(crate) fn output(&mut self, index: usize, err: bool, bytes: usize) {
+        let stream = if err { "stderr" } else { "stdout" };
+        let _ = writeln!(
+            self.writer,
+            r#"{{"event":"output","index":{},"time":{},"stream":"{}","bytes":{}}}"#,
+            index, Time, stream, bytes,
+        );
+    }
+
+    pub(crate) fn finished(&mut self, index: usize, bytes: usize, elapsed: Duration, failed: bool) {
+        let _ = writeln!(
+            self.writer,
+            r#"{{"event":"finished","index":{},"time":{},"bytes":{},"elapsed":{:.6},"failed":{}}}"#,
+            index,
+            Time,
+            bytes,
+            elapsed.as_secs_f64(),
+            failed,
+        );
+        // Each finished task is a good point for a reader following along
+        // to catch up to.
+        let _ = self.writer.flush();
+    }
+}
+
+/// The current time, in seconds since the Unix epoch.
+struct Time;
+
+impl Display for Time {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(formatter, "{}.{:06}", now.as_secs(), now.subsec_micros())
+    }
+}
@@ -0,0 +1,11 @@
+/// Arranges for `crate::sequencer::exiting` to be called when the process
+/// exits normally, by `std::process::exit` or by returning from `main`.
+pub(crate) fn install() {
+    extern "C" fn handle() {
+        crate::sequencer::exiting();
+    }
+
+    unsafe {
+        libc::atexit(handle);
+    }
+}
@@ -0,0 +1,107 @@
+use std::env;
+use std::fmt::{self, Display, Write as _};
+use std::io::{Result, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Markers understood by a CI system, around each task's output, that make
+/// the log collapsible per task.
+///
+/// Refer to [`Sequencer::folding`][crate::Sequencer::folding].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Folding {
+    /// GitHub Actions `::group::` and `::endgroup::` workflow commands.
+    GitHub,
+    /// GitLab CI `section_start` and `section_end` markers, which also let
+    /// GitLab show how long each task took.
+    GitLab,
+    /// TeamCity `blockOpened` and `blockClosed` service messages.
+    TeamCity,
+    /// Buildkite `---` group headers. Buildkite has no marker for the end of
+    /// a group, which instead lasts until the next one begins.
+    Buildkite,
+}
+
+impl Folding {
+    /// The CI system being run under, as determined by the environment
+    /// variables that each of them sets, if it is one that has folding.
+    pub fn detect() -> Option<Self> {
+        if env::var_os("GITHUB_ACTIONS").map_or(false, |var| var == "true") {
+            return Some(Folding::GitHub);
+        }
+        if env::var_os("GITLAB_CI").map_or(false, |var| var == "true") {
+            return Some(Folding::GitLab);
+        }
+        if env::var_os("TEAMCITY_VERSION").is_some() {
+            return Some(Folding::TeamCity);
+        }
+        if env::var_os("BUILDKITE").map_or(false, |var| var == "true") {
+            return Some(Folding::Buildkite);
+        }
+        None
+    }
+
+    /// Writes the marker that begins the fold of a task, at the start of a
+    /// line.
+    pub(crate) fn open(self, w: &mut impl Write, index: usize, title: &str) -> Result<()> {
+        match self {
+            Folding::GitHub => writeln!(w, "::group::{}", title),
+            Folding::GitLab => writeln!(
+                w,
+                "\x1b[0Ksection_start:{}:task_{}[collapsed=true]\r\x1b[0K{}",
+                unix_time(),
+                index,
+                title,
+            ),
+            Folding::TeamCity => {
+                writeln!(w, "##teamcity[blockOpened name='{}']", Escape(title))
+            }
+            Folding::Buildkite => writeln!(w, "--- {}", title),
+        }
+    }
+
+    /// Writes the marker that ends the fold of a task, at the start of a
+    /// line.
+    pub(crate) fn close(self, w: &mut impl Write, index: usize, title: &str) -> Result<()> {
+        match self {
+            Folding::GitHub => writeln!(w, "::endgroup::"),
+            Folding::GitLab => writeln!(
+                w,
+                "\x1b[0Ksection_end:{}:task_{}\r\x1b[0K",
+                unix_time(),
+                index,
+            ),
+            Folding::TeamCity => {
+                writeln!(w, "##teamcity[blockClosed name='{}']", Escape(title))
+            }
+            Folding::Buildkite => Ok(()),
+        }
+    }
+}
+
+/// Value of an attribute of a TeamCity service message, in which `|` is the
+/// escape character.
+struct Escape<'a>(&'a str);
+
+impl Display for Escape<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for ch in self.0.chars() {
+            match ch {
+                '|' => formatter.write_str("||")?,
+                '\'' => formatter.write_str("|'")?,
+                '\n' => formatter.write_str("|n")?,
+                '\r' => formatter.write_str("|r")?,
+                '[' => formatter.write_str("|[")?,
+                ']' => formatter.write_str("|]")?,
+                _ => formatter.write_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
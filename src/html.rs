@@ -0,0 +1,213 @@
+use crate::ansi;
+use crate::junit::Case;
+use crate::palette::CUBE;
+use std::fmt::{self, Display, Write as _};
+use std::io::{Result, Write};
+use termcolor::{Color, ColorSpec};
+
+/// Record of a run, written out as an HTML page once it is over.
+pub(crate) struct Html {
+    writer: Box<dyn Write + Send>,
+    cases: Vec<Case>,
+}
+
+const STYLE: &str = "\
+body { background: #1e1e1e; color: #d4d4d4; font-family: sans-serif; }
+section { margin: 1em 0; border-left: 4px solid #3c3c3c; padding-left: 1em; }
+section.failed { border-left-color: #cd3131; }
+h2 { font-size: 1em; margin: 0; }
+h2 a { color: inherit; text-decoration: none; }
+pre { font-family: monospace; margin: 0.5em 0; white-space: pre-wrap; }
+";
+
+impl Html {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Self {
+        Html {
+            writer,
+            cases: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, case: Case) {
+        self.cases.push(case);
+    }
+
+    /// Writes the page, with the tasks in order of index. Each task's section
+    /// can be linked to as `#task-<index>`.
+    pub(crate) fn write(&mut self) -> Result<()> {
+        self.cases.sort_by_key(|case| case.index);
+        let mut page = String::new();
+        page.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        page.push_str("<title>oqueue</title>\n<style>\n");
+        page.push_str(STYLE);
+        page.push_str("</style>\n</head>\n<body>\n");
+        for case in &self.cases {
+            let class = if case.failed { " class=\"failed\"" } else { "" };
+            let _ = writeln!(page, "<section id=\"task-{}\"{}>", case.index, class);
+            let _ = write!(page, "<h2><a href=\"#task-{}\">", case.index);
+            escape(&mut page, &case.name);
+            page.push_str("</a></h2>\n<pre>");
+            convert(&mut page, &String::from_utf8_lossy(&case.output));
+            page.push_str("</pre>\n</section>\n");
+        }
+        page.push_str("</body>\n</html>\n");
+        self.writer.write_all(page.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+fn escape(out: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+}
+
+/// Turns text colored by ANSI escape sequences into HTML, with a `<span>` for
+/// every stretch of text in some color. Other escape sequences are dropped.
+fn convert(out: &mut String, text: &str) {
+    let mut spec = ColorSpec::new();
+    let mut open = false;
+    let mut rest = text;
+    loop {
+        let esc = rest.find('\x1b').unwrap_or(rest.len());
+        let plain = &rest[..esc];
+        if !plain.is_empty() {
+            if !open && !spec.is_none() {
+                out.push_str("<span style=\"");
+                css(out, &spec);
+                out.push_str("\">");
+                open = true;
+            }
+            escape(out, plain);
+        }
+        rest = match rest.get(esc + 1..) {
+            Some(rest) => rest,
+            None => break,
+        };
+        if let Some(csi) = rest.strip_prefix('[') {
+            let end = csi
+                .find(|ch| ('\x40'..='\x7e').contains(&ch))
+                .unwrap_or(csi.len());
+            if csi[end..].starts_with('m') {
+                ansi::apply_sgr(&mut spec, &csi[..end]);
+                if open {
+                    out.push_str("</span>");
+                    open = false;
+                }
+            }
+            rest = csi.get(end + 1..).unwrap_or("");
+        } else if let Some(osc) = rest.strip_prefix(']') {
+            // Terminated by BEL or by ESC \.
+            let end = osc.find(|ch| ch == '\x07' || ch == '\x1b');
+            rest = match end {
+                Some(end) if osc[end..].starts_with('\x1b') => {
+                    osc[end + 1..].strip_prefix('\\').unwrap_or(&osc[end + 1..])
+                }
+                Some(end) => &osc[end + 1..],
+                None => "",
+            };
+        }
+    }
+    if open {
+        out.push_str("</span>");
+    }
+}
+
+fn css(out: &mut String, spec: &ColorSpec) {
+    if let Some(&fg) = spec.fg() {
+        let _ = write!(out, "color: {}; ", Rgb::of(fg, spec.intense()));
+    }
+    if let Some(&bg) = spec.bg() {
+        let _ = write!(out, "background: {}; ", Rgb::of(bg, spec.intense()));
+    }
+    if spec.bold() {
+        out.push_str("font-weight: bold; ");
+    }
+    if spec.dimmed() {
+        out.push_str("opacity: 0.7; ");
+    }
+    if spec.italic() {
+        out.push_str("font-style: italic; ");
+    }
+    match (spec.underline(), spec.strikethrough()) {
+        (true, true) => out.push_str("text-decoration: underline line-through; "),
+        (true, false) => out.push_str("text-decoration: underline; "),
+        (false, true) => out.push_str("text-decoration: line-through; "),
+        (false, false) => {}
+    }
+    if out.ends_with(' ') {
+        out.pop();
+    }
+}
+
+struct Rgb(u8, u8, u8);
+
+/// The 8 basic colors, followed by their intense variants, as in xterm.
+const BASIC: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+impl Rgb {
+    fn of(color: Color, intense: bool) -> Self {
+        let basic = |n: usize| {
+            let (r, g, b) = BASIC[n + if intense { 8 } else { 0 }];
+            Rgb(r, g, b)
+        };
+        match color {
+            Color::Black => basic(0),
+            Color::Red => basic(1),
+            Color::Green => basic(2),
+            Color::Yellow => basic(3),
+            Color::Blue => basic(4),
+            Color::Magenta => basic(5),
+            Color::Cyan => basic(6),
+            Color::Ansi256(n) => match n {
+                0..=15 => {
+                    let (r, g, b) = BASIC[usize::from(n)];
+                    Rgb(r, g, b)
+                }
+                16..=231 => {
+                    let n = n - 16;
+                    let r = CUBE[usize::from(n / 36)];
+                    let g = CUBE[usize::from(n / 6 % 6)];
+                    let b = CUBE[usize::from(n % 6)];
+                    Rgb(r, g, b)
+                }
+                232..=255 => {
+                    let gray = 8 + 10 * (n - 232);
+                    Rgb(gray, gray, gray)
+                }
+            },
+            Color::Rgb(r, g, b) => Rgb(r, g, b),
+            // White, and any color added to termcolor in the future.
+            _ => basic(7),
+        }
+    }
+}
+
+impl Display for Rgb {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
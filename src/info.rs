@@ -0,0 +1,382 @@
+use std::fmt::{self, Display};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+/// Description of a task that has finished running.
+#[readonly::make]
+#[derive(Debug)]
+pub struct TaskInfo {
+    /// Index of the task, the same as `task.index` of the Task it describes.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub index: usize,
+
+    /// Thread on which the task finished running, which is the thread that
+    /// dropped the Task.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub thread: ThreadId,
+
+    /// Number of bytes written to the task, before any decoration or removal
+    /// of color.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub bytes: usize,
+
+    /// Time from when the task was begun until it finished.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub elapsed: Duration,
+
+    /// Whether the task was marked as failed by [`Task::set_failed`].
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    ///
+    /// [`Task::set_failed`]: crate::Task::set_failed
+    #[readonly]
+    pub failed: bool,
+}
+
+impl TaskInfo {
+    pub(crate) fn new(
+        index: usize,
+        thread: ThreadId,
+        bytes: usize,
+        elapsed: Duration,
+        failed: bool,
+    ) -> Self {
+        TaskInfo {
+            index,
+            thread,
+            bytes,
+            elapsed,
+            failed,
+        }
+    }
+}
+
+/// Summary of the tasks performed within a [`Scope`][crate::Scope], or by a
+/// whole [`Sequencer`][crate::Sequencer] so far.
+#[readonly::make]
+#[derive(Debug)]
+pub struct Summary {
+    /// Number of tasks begun.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub tasks: usize,
+
+    /// Number of finished tasks that were not marked as failed.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub passed: usize,
+
+    /// Number of finished tasks that were marked as failed by
+    /// [`Task::set_failed`][crate::Task::set_failed].
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub failed: usize,
+
+    /// Threads that finished tasks, in the order that each one first finished
+    /// a task, with how many tasks each of them finished. A skewed
+    /// distribution indicates workers sitting idle while others are busy.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub workers: Vec<Worker>,
+}
+
+impl Summary {
+    pub(crate) fn new(tasks: usize, before: &Tally, after: &Tally) -> Self {
+        Summary {
+            tasks,
+            passed: after.passed - before.passed,
+            failed: after.failed - before.failed,
+            workers: Worker::since(&before.workers, &after.workers),
+        }
+    }
+
+    /// Whether none of the tasks failed.
+    pub fn success(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+/// Running count of finished tasks.
+#[derive(Clone, Default)]
+pub(crate) struct Tally {
+    passed: usize,
+    failed: usize,
+    workers: Vec<Worker>,
+}
+
+impl Tally {
+    pub(crate) fn passed(&self) -> usize {
+        self.passed
+    }
+
+    pub(crate) fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Counts a task finished by the current thread.
+    pub(crate) fn record(&mut self, failed: bool) -> ThreadId {
+        if failed {
+            self.failed += 1;
+        } else {
+            self.passed += 1;
+        }
+
+        let current = thread::current();
+        let thread = current.id();
+        if let Some(worker) = self
+            .workers
+            .iter_mut()
+            .find(|worker| worker.thread == thread)
+        {
+            worker.tasks += 1;
+        } else {
+            self.workers.push(Worker {
+                thread,
+                name: current.name().map(str::to_owned),
+                tasks: 1,
+            });
+        }
+        thread
+    }
+}
+
+/// Number of tasks finished by one thread.
+#[readonly::make]
+#[derive(Clone, Debug)]
+pub struct Worker {
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub thread: ThreadId,
+
+    /// Name of the thread, if it has one.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub name: Option<String>,
+
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub tasks: usize,
+}
+
+impl Worker {
+    /// Tasks finished by each worker since the `before` snapshot was taken.
+    fn since(before: &[Worker], after: &[Worker]) -> Vec<Worker> {
+        let mut workers = Vec::new();
+        for worker in after {
+            let previously = before
+                .iter()
+                .find(|earlier| earlier.thread == worker.thread)
+                .map_or(0, |earlier| earlier.tasks);
+            if worker.tasks > previously {
+                workers.push(Worker {
+                    tasks: worker.tasks - previously,
+                    ..worker.clone()
+                });
+            }
+        }
+        workers
+    }
+}
+
+/// What the output of a Sequencer is waiting on, as reported by
+/// [`Sequencer::dump_state`][crate::Sequencer::dump_state].
+///
+/// The `Display` impl prints it in a form meant for people, one line per
+/// task.
+#[readonly::make]
+#[derive(Debug)]
+pub struct QueueState {
+    /// Index of the task whose output is printed next, which is holding up
+    /// the output of all the tasks after it, if there is one that is not yet
+    /// finished.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub head: Option<usize>,
+
+    /// Tasks that have not been printed in their entirety yet, in the order
+    /// they are going to be printed, beginning with the head.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub tasks: Vec<PendingTask>,
+}
+
+/// Task whose output has not all been printed yet, in a [`QueueState`].
+#[readonly::make]
+#[derive(Debug)]
+pub struct PendingTask {
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub index: usize,
+
+    /// Whether the task has been begun. A task with a higher index can be
+    /// begun first, by [`Sequencer::begin_at`][crate::Sequencer::begin_at].
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub begun: bool,
+
+    /// Whether the task has finished.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub done: bool,
+
+    /// Time since the task was begun, or from when it was begun until it
+    /// finished.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub elapsed: Duration,
+
+    /// Number of bytes of the task's output held in memory waiting to be
+    /// printed.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub buffered: usize,
+}
+
+/// Counts of the tasks of a [`Sequencer`][crate::Sequencer], returned by
+/// [`Sequencer::stats`][crate::Sequencer::stats].
+#[readonly::make]
+#[derive(Debug)]
+pub struct Stats {
+    /// Number of tasks begun.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub started: usize,
+
+    /// Number of tasks that have finished running, whether or not their
+    /// output has been printed yet.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub finished: usize,
+
+    /// Number of tasks that have finished running but whose output is waiting
+    /// for that of an earlier task to be printed first.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub buffered: usize,
+
+    /// Number of bytes written to all the tasks.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub bytes: u64,
+
+    /// Number of tasks that have been begun but whose output has not all been
+    /// printed yet.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub pending: usize,
+
+    /// Most tasks that have been pending at any one time.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub peak_pending: usize,
+
+    /// Number of bytes of output held in memory, waiting for their turn to
+    /// be printed. Output that has been [moved to
+    /// disk][crate::Sequencer::spill_to_disk] does not count.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub memory: usize,
+
+    /// Most bytes of output that have been held in memory at any one time.
+    ///
+    /// This field is read-only; writing to its value will not compile.
+    #[readonly]
+    pub peak_memory: usize,
+}
+
+impl Stats {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        started: usize,
+        finished: usize,
+        buffered: usize,
+        bytes: u64,
+        pending: usize,
+        peak_pending: usize,
+        memory: usize,
+        peak_memory: usize,
+    ) -> Self {
+        Stats {
+            started,
+            finished,
+            buffered,
+            bytes,
+            pending,
+            peak_pending,
+            memory,
+            peak_memory,
+        }
+    }
+}
+
+impl QueueState {
+    pub(crate) fn new(tasks: Vec<PendingTask>) -> Self {
+        let head = tasks.iter().find(|task| !task.done).map(|task| task.index);
+        QueueState { head, tasks }
+    }
+}
+
+impl PendingTask {
+    pub(crate) fn new(
+        index: usize,
+        begun: bool,
+        done: bool,
+        elapsed: Duration,
+        buffered: usize,
+    ) -> Self {
+        PendingTask {
+            index,
+            begun,
+            done,
+            elapsed,
+            buffered,
+        }
+    }
+}
+
+impl Display for QueueState {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.head {
+            Some(head) => writeln!(formatter, "waiting on task {}", head)?,
+            None => writeln!(formatter, "not waiting on any task")?,
+        }
+        for task in &self.tasks {
+            write!(formatter, "  task {}: ", task.index)?;
+            if !task.begun {
+                write!(formatter, "not begun")?;
+            } else if task.done {
+                write!(formatter, "done after {:.1}s", task.elapsed.as_secs_f64())?;
+            } else {
+                write!(formatter, "running for {:.1}s", task.elapsed.as_secs_f64())?;
+            }
+            writeln!(formatter, ", {} bytes buffered", task.buffered)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Result;
+
+/// Arranges for `crate::sequencer::interrupted` to be called when the user
+/// presses Ctrl-C, after which the process goes on to be ended the way it
+/// would have been without oqueue.
+#[cfg(unix)]
+pub(crate) fn install() -> Result<()> {
+    use std::fs::File;
+    use std::io::{Error, Read};
+    use std::os::raw::c_int;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::{mem, ptr, thread};
+
+    // Written to by the signal handler, which can do little else safely, to
+    // wake up a thread that does the actual work.
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle(_signal: c_int) {
+        let byte = 0u8;
+        let fd = PIPE.load(Ordering::Relaxed);
+        unsafe {
+            libc::write(fd, ptr::addr_of!(byte).cast(), 1);
+        }
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+    PIPE.store(fds[1], Ordering::Relaxed);
+
+    thread::Builder::new()
+        .name("oqueue-interrupt".to_owned())
+        .spawn(move || {
+            if reader.read_exact(&mut [0]).is_err() {
+                return;
+            }
+            crate::sequencer::interrupted();
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::raise(libc::SIGINT);
+            }
+        })?;
+
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handle as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGINT, &action, ptr::null_mut()) != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Arranges for `crate::sequencer::interrupted` to be called when the user
+/// presses Ctrl-C or Ctrl-Break, after which the process goes on to be ended
+/// the way it would have been without oqueue.
+#[cfg(windows)]
+pub(crate) fn install() -> Result<()> {
+    use std::io::Error;
+    use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+    };
+
+    // Called on a thread of its own, so unlike a signal handler it is free to
+    // take locks.
+    unsafe extern "system" fn handle(event: u32) -> BOOL {
+        if event == CTRL_C_EVENT || event == CTRL_BREAK_EVENT {
+            crate::sequencer::interrupted();
+        }
+        // Leave it to the next handler, by default the one that ends the
+        // process.
+        FALSE
+    }
+
+    if unsafe { SetConsoleCtrlHandler(Some(handle), TRUE) } == FALSE {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn install() -> Result<()> {
+    Ok(())
+}
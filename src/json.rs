@@ -0,0 +1,57 @@
+use crate::ansi::Stripper;
+use std::fmt::{self, Display, Write as _};
+use std::io::{Result, Write};
+use std::time::Duration;
+
+/// What is known about a finished task, reported as one line of JSON.
+pub(crate) struct Finished<'a> {
+    pub(crate) index: usize,
+    pub(crate) title: Option<&'a str>,
+    pub(crate) failed: bool,
+    pub(crate) cancelled: bool,
+    pub(crate) elapsed: Duration,
+    pub(crate) output: &'a [u8],
+}
+
+impl Finished<'_> {
+    pub(crate) fn write(&self, w: &mut impl Write) -> Result<()> {
+        let mut plain = Vec::new();
+        Stripper::new().strip(self.output, &mut plain);
+        let output = String::from_utf8_lossy(&plain);
+
+        write!(w, r#"{{"index":{},"title":"#, self.index)?;
+        match self.title {
+            Some(title) => write!(w, "{}", Str(title))?,
+            None => write!(w, "null")?,
+        }
+        writeln!(
+            w,
+            r#","failed":{},"cancelled":{},"elapsed":{:.6},"output":{}}}"#,
+            self.failed,
+            self.cancelled,
+            self.elapsed.as_secs_f64(),
+            Str(&output),
+        )
+    }
+}
+
+/// A JSON string, with quotes.
+pub(crate) struct Str<'a>(pub(crate) &'a str);
+
+impl Display for Str<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_char('"')?;
+        for ch in self.0.chars() {
+            match ch {
+                '"' => formatter.write_str("\\\"")?,
+                '\\' => formatter.write_str("\\\\")?,
+                '\n' => formatter.write_str("\\n")?,
+                '\r' => formatter.write_str("\\r")?,
+                '\t' => formatter.write_str("\\t")?,
+                '\u{0}'..='\u{1f}' => write!(formatter, "\\u{:04x}", u32::from(ch))?,
+                _ => formatter.write_char(ch)?,
+            }
+        }
+        formatter.write_char('"')
+    }
+}
@@ -0,0 +1,110 @@
+use crate::ansi::Stripper;
+use std::fmt::{self, Display, Write as _};
+use std::io::{Result, Write};
+use std::time::{Duration, Instant};
+
+/// Record of a run, written out as a JUnit XML report once it is over.
+pub(crate) struct JUnit {
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+    cases: Vec<Case>,
+}
+
+/// Outcome of one finished task.
+#[derive(Clone)]
+pub(crate) struct Case {
+    pub(crate) index: usize,
+    pub(crate) name: String,
+    pub(crate) elapsed: Duration,
+    pub(crate) failed: bool,
+    pub(crate) skipped: bool,
+    /// Everything the task wrote, to either of its streams.
+    pub(crate) output: Vec<u8>,
+}
+
+impl JUnit {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Self {
+        JUnit {
+            writer,
+            start: Instant::now(),
+            cases: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, case: Case) {
+        self.cases.push(case);
+    }
+
+    /// Writes the report, with the tasks in order of index.
+    pub(crate) fn write(&mut self) -> Result<()> {
+        self.cases.sort_by_key(|case| case.index);
+        let tests = self.cases.len();
+        let failures = self.cases.iter().filter(|case| case.failed).count();
+        let skipped = self.cases.iter().filter(|case| case.skipped).count();
+        let time = self.start.elapsed().as_secs_f64();
+
+        let w = &mut self.writer;
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<testsuites tests="{}" failures="{}" time="{:.3}">"#,
+            tests, failures, time,
+        )?;
+        writeln!(
+            w,
+            r#"  <testsuite name="oqueue" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+            tests, failures, skipped, time,
+        )?;
+        for case in &self.cases {
+            write!(
+                w,
+                r#"    <testcase name="{}" classname="oqueue" time="{:.3}""#,
+                Escape(&case.name),
+                case.elapsed.as_secs_f64(),
+            )?;
+            if !case.failed && !case.skipped && case.output.is_empty() {
+                writeln!(w, "/>")?;
+                continue;
+            }
+            writeln!(w, ">")?;
+            if case.failed {
+                writeln!(w, "      <failure/>")?;
+            }
+            if case.skipped {
+                writeln!(w, "      <skipped/>")?;
+            }
+            if !case.output.is_empty() {
+                let mut plain = Vec::new();
+                Stripper::new().strip(&case.output, &mut plain);
+                let text = String::from_utf8_lossy(&plain);
+                writeln!(w, "      <system-out>{}</system-out>", Escape(&text))?;
+            }
+            writeln!(w, "    </testcase>")?;
+        }
+        writeln!(w, "  </testsuite>")?;
+        writeln!(w, "</testsuites>")?;
+        w.flush()
+    }
+}
+
+/// Text escaped for use in XML content or in an attribute value.
+struct Escape<'a>(&'a str);
+
+impl Display for Escape<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for ch in self.0.chars() {
+            match ch {
+                '&' => formatter.write_str("&amp;")?,
+                '<' => formatter.write_str("&lt;")?,
+                '>' => formatter.write_str("&gt;")?,
+                '"' => formatter.write_str("&quot;")?,
+                '\'' => formatter.write_str("&apos;")?,
+                // Other control characters cannot appear in XML 1.0 at all.
+                '\t' | '\n' | '\r' => formatter.write_char(ch)?,
+                _ if ch.is_control() => {}
+                _ => formatter.write_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -148,17 +148,122 @@
 //! <br>
 
 #![doc(html_root_url = "https://docs.rs/oqueue/0.1.8")]
+#![cfg_attr(docsrs, feature(doc_cfg))]
 #![allow(
+    clippy::doc_markdown,
     clippy::let_underscore_untyped,
     clippy::module_name_repetitions,
     clippy::must_use_candidate,
     clippy::redundant_closure_for_method_calls
 )]
 
+mod ansi;
+mod archive;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub mod asynchronous;
+mod capture;
+mod current;
+mod events;
+#[cfg(feature = "exit")]
+mod exit;
+mod folding;
+mod html;
+mod info;
+#[cfg(feature = "interrupt")]
+mod interrupt;
+mod json;
+mod junit;
+mod limit;
+#[cfg(feature = "log")]
+mod logger;
+mod messages;
+#[cfg(feature = "opentelemetry")]
+mod otel;
+mod palette;
+mod panic;
+#[cfg(feature = "rayon")]
+mod parallel;
 mod sequencer;
+mod sink;
+mod spill;
+mod style;
 mod sync;
+mod tap;
+#[cfg(feature = "metrics")]
+mod telemetry;
+mod theme;
+mod timestamp;
+#[cfg(all(feature = "tracing", feature = "tracing-subscriber"))]
+mod trace;
+mod truncate;
+mod watchdog;
 
-pub use crate::sequencer::{Sequencer, Task};
+pub use crate::capture::Capture;
+#[doc(hidden)]
+pub use crate::current::__print;
+pub use crate::current::Entered;
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub use crate::current::{__TaskGuard, __enter_task};
+pub use crate::folding::Folding;
+pub use crate::info::{PendingTask, QueueState, Stats, Summary, TaskInfo, Worker};
+#[cfg(feature = "log")]
+#[cfg_attr(docsrs, doc(cfg(feature = "log")))]
+pub use crate::logger::TaskLogger;
+pub use crate::messages::Messages;
+pub use crate::palette::ColorDepth;
+#[cfg(feature = "rayon")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+pub use crate::parallel::ParallelIteratorExt;
+pub use crate::sequencer::{Atomic, Scope, Section, Sequencer, SequencerBuilder, Task, TaskWriter};
+pub use crate::style::{StyleBuilder, StyleGuard, ToColorSpec};
+pub use crate::theme::{Severity, Theme};
+pub use crate::timestamp::Timestamps;
+pub use crate::truncate::{truncate, truncate_start};
+
+#[cfg(all(feature = "tracing", feature = "tracing-subscriber"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(all(feature = "tracing", feature = "tracing-subscriber")))
+)]
+pub use crate::trace::TaskLayer;
+
+/// Runs each call of the function as a task of the given Sequencer.
+///
+/// The task is begun when the function is called, [entered][Task::enter] as
+/// the current task of the thread for the duration of the call, so that
+/// [`oqueue::println!`][println!] and the log adapter write to it, and
+/// finished when the function returns or panics. If the function is called
+/// while a task of the same Sequencer is already current, such as from
+/// another function with this attribute, it runs as part of that task
+/// instead of beginning its own.
+///
+/// The argument is any expression evaluating to a `Sequencer` or a reference
+/// to one, such as a static or one of the function's parameters.
+///
+/// ```
+/// use oqueue::Sequencer;
+///
+/// static OQUEUE: Sequencer = Sequencer::lazy_stderr();
+///
+/// #[oqueue::task(OQUEUE)]
+/// fn compile(name: &str) -> bool {
+///     oqueue::println!("compiling {}", name);
+///     link(name)
+/// }
+///
+/// #[oqueue::task(OQUEUE)]
+/// fn link(name: &str) -> bool {
+///     oqueue::println!("linking {}", name);
+///     true
+/// }
+///
+/// assert!(compile("foo"));
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use oqueue_impl::task;
 
 #[doc(no_inline)]
-pub use termcolor::Color;
+pub use termcolor::{Color, ColorChoice, ColorSpec, HyperlinkSpec};
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Decides which lines of a task's output to keep, under a cap on the number
+/// of lines and on the rate of bytes.
+pub(crate) struct Limiter {
+    max_lines: Option<usize>,
+    /// Bytes per second, which is also the most that can be written in one
+    /// burst.
+    rate: Option<usize>,
+    /// Lines kept so far.
+    lines: usize,
+    /// Lines dropped since last reported.
+    dropped: usize,
+    /// Whether the most recent piece did not finish its line.
+    continuing: bool,
+    /// Whether the line currently being written is being dropped.
+    dropping: bool,
+    /// Bytes that may be written right now without exceeding the rate, in
+    /// billionths of a byte so that even a short time adds some.
+    allowance: u128,
+    refilled: Instant,
+}
+
+const NANOS: u128 = 1_000_000_000;
+
+impl Limiter {
+    pub(crate) fn new(max_lines: Option<usize>, rate: Option<usize>) -> Option<Self> {
+        if max_lines.is_none() && rate.is_none() {
+            return None;
+        }
+        Some(Limiter {
+            max_lines,
+            rate,
+            lines: 0,
+            dropped: 0,
+            continuing: false,
+            dropping: false,
+            allowance: rate.map_or(0, |rate| rate as u128 * NANOS),
+            refilled: Instant::now(),
+        })
+    }
+
+    /// Whether to keep a piece of a line, at most one line long. Lines are
+    /// kept or dropped as a whole.
+    pub(crate) fn admit(&mut self, piece: &[u8]) -> bool {
+        let continuation = self.continuing;
+        self.continuing = !piece.ends_with(b"\n");
+        let cost = piece.len() as u128 * NANOS;
+        if continuation {
+            // The rest of a line that was let through cannot be dropped, but
+            // counts against the lines after it.
+            if !self.dropping && self.rate.is_some() {
+                self.allowance = self.refill().saturating_sub(cost);
+            }
+            return !self.dropping;
+        }
+
+        let mut keep = self.max_lines.map_or(true, |max| self.lines < max);
+        if let (Some(rate), true) = (self.rate, keep) {
+            let burst = rate as u128 * NANOS;
+            let allowance = self.refill();
+            // A line longer than the whole burst gets through only when
+            // nothing else has been written for a second.
+            keep = cost <= allowance || allowance == burst;
+            self.allowance = if keep {
+                allowance.saturating_sub(cost)
+            } else {
+                allowance
+            };
+        }
+
+        if keep {
+            self.lines += 1;
+        } else {
+            self.dropped += 1;
+        }
+        self.dropping = !keep;
+        keep
+    }
+
+    /// Allowance after adding what accrued since the last piece.
+    fn refill(&mut self) -> u128 {
+        let rate = self.rate.unwrap_or(0) as u128;
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_nanos();
+        self.refilled = now;
+        (self.allowance + elapsed * rate).min(rate * NANOS)
+    }
+
+    /// Notes that the line in progress was ended by something other than the
+    /// task, so the next piece begins a line of its own.
+    pub(crate) fn interrupt(&mut self) {
+        self.continuing = false;
+    }
+
+    /// Number of lines dropped since the last call.
+    pub(crate) fn take_dropped(&mut self) -> usize {
+        let dropped = self.dropped;
+        self.dropped = 0;
+        dropped
+    }
+}
+
+/// Holds back the lines of a task's output past the first `head`, keeping
+/// only the last `tail` of them, to be written at the end in place of the
+/// rest.
+pub(crate) struct Elision {
+    head: usize,
+    tail: usize,
+    /// Complete lines let through so far, up to `head`.
+    lines: usize,
+    /// Latest lines held back, the last of which may be incomplete.
+    held: VecDeque<Vec<u8>>,
+    omitted: usize,
+}
+
+impl Elision {
+    pub(crate) fn new(head: usize, tail: usize) -> Self {
+        Elision {
+            head,
+            tail,
+            lines: 0,
+            held: VecDeque::new(),
+            omitted: 0,
+        }
+    }
+
+    /// Takes what is past the first `head` lines out of output that was just
+    /// written, returning the length of what is left.
+    pub(crate) fn hold(&mut self, written: &[u8]) -> usize {
+        let mut len = 0;
+        while self.lines < self.head && len < written.len() {
+            match written[len..].iter().position(|&b| b == b'\n') {
+                Some(newline) => {
+                    len += newline + 1;
+                    self.lines += 1;
+                }
+                None => len = written.len(),
+            }
+        }
+
+        for piece in written[len..].split_inclusive(|&b| b == b'\n') {
+            match self.held.back_mut() {
+                Some(last) if !last.ends_with(b"\n") => last.extend_from_slice(piece),
+                _ => self.held.push_back(piece.to_vec()),
+            }
+            let incomplete = !piece.ends_with(b"\n");
+            while self.held.len() - usize::from(incomplete) > self.tail {
+                self.held.pop_front();
+                self.omitted += 1;
+            }
+        }
+        len
+    }
+
+    /// Number of lines left out, and the last lines, to be written after the
+    /// notice of that number.
+    pub(crate) fn finish(self) -> (usize, Vec<u8>) {
+        (self.omitted, self.held.into_iter().flatten().collect())
+    }
+}
@@ -0,0 +1,67 @@
+use crate::current;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::io::{self, Write};
+
+/// Logger for the `log` crate that writes records into the
+/// [current task][crate::Task::enter] of the thread doing the logging.
+///
+/// Records logged while no task is current are printed to stderr directly.
+///
+/// ```
+/// use oqueue::{Sequencer, TaskLogger};
+///
+/// TaskLogger::install(log::LevelFilter::Info).unwrap();
+///
+/// let oqueue = Sequencer::stderr();
+/// let task = oqueue.begin();
+/// let _entered = task.enter();
+/// log::info!("sequenced along with the rest of the task's output");
+/// ```
+#[derive(Debug)]
+pub struct TaskLogger {
+    _private: (),
+}
+
+static LOGGER: TaskLogger = TaskLogger { _private: () };
+
+impl TaskLogger {
+    /// Installs the logger as the global logger, with records more verbose
+    /// than `max_level` filtered out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a global logger has already been installed.
+    pub fn install(max_level: LevelFilter) -> Result<(), SetLoggerError> {
+        log::set_logger(&LOGGER)?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl Log for TaskLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{:>5} {}: {}\n",
+            record.level(),
+            record.target(),
+            record.args(),
+        );
+        current::with_current(|task| match task {
+            Some(task) => {
+                let _ = task.clone().write_all(line.as_bytes());
+            }
+            None => {
+                let _ = io::stderr().write_all(line.as_bytes());
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
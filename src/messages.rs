@@ -0,0 +1,201 @@
+use crate::theme::Severity;
+use std::time::Duration;
+
+/// Wording of the text that oqueue itself adds to the output.
+///
+/// Several optional features of the Sequencer print text of their own, on top
+/// of what tasks write. All of that text is obtained from the methods of this
+/// trait, each of which has a default implementation in English. Applications
+/// whose output is localized can override whichever methods they need to
+/// translate or reword, and install the implementation on a Sequencer using
+/// [`Sequencer::messages`][crate::Sequencer::messages].
+///
+/// ```
+/// use oqueue::{Messages, Sequencer};
+///
+/// struct Deutsch;
+///
+/// impl Messages for Deutsch {
+///     fn summary(&self, passed: usize, failed: usize) -> String {
+///         format!("{} bestanden, {} fehlgeschlagen", passed, failed)
+///     }
+/// }
+///
+/// let oqueue = Sequencer::stderr().messages(Deutsch);
+/// ```
+pub trait Messages: Send {
+    /// Line printed at the end of the run by
+    /// [`Sequencer::print_summary`][crate::Sequencer::print_summary].
+    fn summary(&self, passed: usize, failed: usize) -> String {
+        format!("{} passed, {} failed", passed, failed)
+    }
+
+    /// Status line kept at the bottom of the terminal by
+    /// [`Sequencer::status_footer`][crate::Sequencer::status_footer]. `live`
+    /// is the index of the task currently printing in real time, if any.
+    fn status(&self, done: usize, running: usize, live: Option<usize>) -> String {
+        match live {
+            Some(index) => format!("{} done, {} running, task {} live", done, running, index),
+            None => format!("{} done, {} running", done, running),
+        }
+    }
+
+    /// Label at the beginning of a diagnostic written by
+    /// [`Task::error`][crate::Task::error] or its siblings, not including the
+    /// colon that follows it.
+    fn label(&self, severity: Severity) -> String {
+        let label = match severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+            Severity::Note => "note",
+        };
+        label.to_owned()
+    }
+
+    /// Marker written by [`Task::status_ok`][crate::Task::status_ok] if `ok`,
+    /// or by [`Task::status_fail`][crate::Task::status_fail] otherwise.
+    /// `unicode` is whether the terminal is able to render characters beyond
+    /// ASCII.
+    fn marker(&self, ok: bool, unicode: bool) -> String {
+        let marker = match (ok, unicode) {
+            (true, true) => "✓",
+            (false, true) => "✗",
+            (true, false) => "[ok]",
+            (false, false) => "[FAIL]",
+        };
+        marker.to_owned()
+    }
+
+    /// Name of a task that has no [title][crate::Task::set_title], for the
+    /// fold around its output in a CI log made by
+    /// [`Sequencer::folding`][crate::Sequencer::folding] and for its test case
+    /// in [`Sequencer::junit_report`][crate::Sequencer::junit_report].
+    fn fold_title(&self, index: usize) -> String {
+        format!("task {}", index)
+    }
+
+    /// Line ending the output of every task, printed by
+    /// [`Sequencer::print_durations`][crate::Sequencer::print_durations].
+    fn duration(&self, elapsed: Duration) -> String {
+        format!("(finished in {:.1}s)", elapsed.as_secs_f64())
+    }
+
+    /// Line following a line that was written `times` times in a row, in
+    /// place of the repeats, printed by
+    /// [`Sequencer::dedup_lines`][crate::Sequencer::dedup_lines].
+    fn repeated(&self, times: usize) -> String {
+        format!("(repeated {} times)", times)
+    }
+
+    /// Line ending the output of a task that wrote more than it was allowed
+    /// to, saying how many of its lines were left out, printed under
+    /// [`Sequencer::max_lines`][crate::Sequencer::max_lines] and
+    /// [`Sequencer::max_bytes_per_second`][crate::Sequencer::max_bytes_per_second].
+    fn dropped(&self, lines: usize) -> String {
+        if lines == 1 {
+            "(1 line dropped)".to_owned()
+        } else {
+            format!("({} lines dropped)", lines)
+        }
+    }
+
+    /// Line in place of the middle of a task's output, saying how many lines
+    /// were left out, printed under
+    /// [`Sequencer::elide`][crate::Sequencer::elide]. `unicode` is whether
+    /// the terminal is able to render characters beyond ASCII.
+    fn omitted(&self, lines: usize, unicode: bool) -> String {
+        let mut digits = lines.to_string();
+        let mut i = digits.len();
+        while i > 3 {
+            i -= 3;
+            digits.insert(i, ',');
+        }
+        let ellipsis = if unicode { "…" } else { "..." };
+        let noun = if lines == 1 { "line" } else { "lines" };
+        format!("{} {} {} omitted {}", ellipsis, digits, noun, ellipsis)
+    }
+
+    /// Line beginning the part of a task's output that was kept, saying how
+    /// many bytes before it were thrown away, printed under
+    /// [`Sequencer::keep_tail`][crate::Sequencer::keep_tail].
+    fn trimmed(&self, bytes: usize) -> String {
+        format!("({} bytes of earlier output discarded)", bytes)
+    }
+
+    /// Message of a panic that happened in a task, captured under
+    /// [`Sequencer::capture_panics`][crate::Sequencer::capture_panics].
+    /// `location` is the source file, line and column of the panic, if known.
+    fn panicked(&self, message: &str, location: Option<&str>) -> String {
+        match location {
+            Some(location) => format!("panicked at {}:\n{}", location, message),
+            None => format!("panicked:\n{}", message),
+        }
+    }
+
+    /// Notice that the task whose output is printed next has been silent for
+    /// a while, printed by
+    /// [`Sequencer::watchdog`][crate::Sequencer::watchdog]. `queued` is how
+    /// many tasks after it have been begun.
+    fn still_running(&self, index: usize, elapsed: Duration, queued: usize) -> String {
+        let elapsed = hms(elapsed);
+        match queued {
+            0 => format!("task {} still running ({} elapsed)", index, elapsed),
+            1 => format!(
+                "task {} still running ({} elapsed, 1 task queued behind it)",
+                index, elapsed,
+            ),
+            _ => format!(
+                "task {} still running ({} elapsed, {} tasks queued behind it)",
+                index, elapsed, queued,
+            ),
+        }
+    }
+
+    /// Line showing that the program is still alive after printing nothing
+    /// for a while, printed by
+    /// [`Sequencer::keepalive`][crate::Sequencer::keepalive].
+    fn keepalive(&self, silent: Duration) -> String {
+        format!("(no output for {}s)", silent.as_secs())
+    }
+
+    /// Line added to the output of a task that ran for longer than
+    /// [`Sequencer::timeout`][crate::Sequencer::timeout] allows.
+    fn timed_out(&self, timeout: Duration) -> String {
+        format!("timed out after {}", hms(timeout))
+    }
+
+    /// Line at the end of the run saying the most output that was held in
+    /// memory at once, printed by
+    /// [`Sequencer::print_peak_memory`][crate::Sequencer::print_peak_memory].
+    fn peak_memory(&self, bytes: usize) -> String {
+        format!("at most {} bytes of output held in memory", bytes)
+    }
+
+    /// Line ending the output of a task that has a title, printed by
+    /// [`Sequencer::title_footers`][crate::Sequencer::title_footers].
+    fn title_footer(&self, title: &str, failed: bool) -> String {
+        if failed {
+            format!("failed {}", title)
+        } else {
+            format!("finished {}", title)
+        }
+    }
+}
+
+/// Duration such as `5m12s`, to the second.
+fn hms(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// The default wording.
+pub(crate) struct English;
+
+impl Messages for English {}
@@ -0,0 +1,51 @@
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{Span, Status, Tracer};
+use opentelemetry::KeyValue;
+use std::convert::TryFrom;
+
+/// OpenTelemetry span covering a task from when it is begun until it is
+/// finished, under [`Sequencer::export_spans`].
+///
+/// [`Sequencer::export_spans`]: crate::Sequencer::export_spans
+pub(crate) struct TaskSpan {
+    span: BoxedSpan,
+}
+
+impl TaskSpan {
+    /// Starts a span as a child of the current context of the thread that is
+    /// beginning the task.
+    pub(crate) fn start(index: usize) -> Self {
+        let tracer = global::tracer("oqueue");
+        let mut span = tracer.start("oqueue.task");
+        span.set_attribute(KeyValue::new(
+            "oqueue.index",
+            i64::try_from(index).unwrap_or(i64::MAX),
+        ));
+        TaskSpan { span }
+    }
+
+    /// Ends the span, recording how the task turned out.
+    pub(crate) fn end(mut self, title: Option<&str>, failed: bool, cancelled: bool, bytes: usize) {
+        if let Some(title) = title {
+            self.span
+                .set_attribute(KeyValue::new("oqueue.title", title.to_owned()));
+        }
+        let status = if cancelled {
+            "cancelled"
+        } else if failed {
+            "failed"
+        } else {
+            "passed"
+        };
+        self.span
+            .set_attribute(KeyValue::new("oqueue.status", status));
+        self.span.set_attribute(KeyValue::new(
+            "oqueue.bytes",
+            i64::try_from(bytes).unwrap_or(i64::MAX),
+        ));
+        if failed {
+            self.span.set_status(Status::error("task failed"));
+        }
+        self.span.end();
+    }
+}
@@ -0,0 +1,449 @@
+use super::{Fold, Output};
+use crate::ansi;
+use crate::folding::Folding;
+use crate::truncate;
+use std::fmt::Display;
+use std::io::{Result, Write};
+use std::mem;
+use std::str;
+use termcolor::{Buffer, Color, ColorSpec, HyperlinkSpec, WriteColor};
+
+const INDENT: &[u8] = b"  ";
+
+/// Colors cycled through for the line prefixes of interleaved tasks, unless
+/// the Sequencer is given others.
+pub(super) const PREFIX_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Whether buffers made from the empty buffer `blank` are for the legacy
+/// Windows console, which is colored through the console API rather than by
+/// escape sequences. Such a buffer keeps colors apart from the text, so
+/// setting a color leaves the text as it is.
+pub(super) fn is_console(blank: &Buffer) -> bool {
+    if !cfg!(windows) || !blank.supports_color() {
+        return false;
+    }
+    let mut scratch = blank.clone();
+    scratch.clear();
+    let mut spec = ColorSpec::new();
+    spec.set_bold(true);
+    scratch.set_color(&spec).is_ok() && scratch.is_empty()
+}
+
+impl Output {
+    pub(super) fn begin_section(&mut self, title: &dyn Display) -> Result<()> {
+        let mut spec = ColorSpec::new();
+        spec.set_bold(true);
+        self.line(&spec, title)?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub(super) fn title_line(&mut self, title: &str) -> Result<()> {
+        let mut spec = ColorSpec::new();
+        spec.set_bold(true);
+        self.line(&spec, &title)
+    }
+
+    /// Writes the marker beginning a fold, if this is the first output of the
+    /// task.
+    pub(super) fn open_fold(&mut self, folding: Folding, title: &str) -> Result<()> {
+        if let Fold::Unopened = self.fold {
+            folding.open(&mut self.buffer, self.index, title)?;
+            self.fold = Fold::Open(folding, title.to_owned());
+        }
+        Ok(())
+    }
+
+    /// Writes the marker ending the fold, if one was begun. This takes the
+    /// place of resetting the color at the end of the task.
+    pub(super) fn close_fold(&mut self) -> Result<()> {
+        if let Fold::Open(folding, title) = mem::replace(&mut self.fold, Fold::Closed) {
+            // Nothing may come before the marker on its line, not even an
+            // escape sequence, so a color still in effect is reset on the line
+            // before.
+            let styled = !self.style.is_none() && self.buffer.supports_color();
+            if styled {
+                self.buffer.reset()?;
+                self.style = ColorSpec::new();
+            }
+            if styled || !self.line_start {
+                self.buffer.write_all(b"\n")?;
+            }
+            self.line_start = true;
+            folding.close(&mut self.buffer, self.index, &title)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the line reporting the outcome of a task that has a title.
+    pub(super) fn title_footer(&mut self, line: &str) -> Result<()> {
+        let color = if self.failed {
+            Color::Red
+        } else {
+            Color::Green
+        };
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(color));
+        self.line(&spec, &line)
+    }
+
+    /// Writes the message of a panic in the task.
+    pub(super) fn panicked(&mut self, line: &str) -> Result<()> {
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(Color::Red));
+        self.line(&spec, &line)
+    }
+
+    /// Writes the line saying that the task took too long, and marks it
+    /// failed.
+    pub(super) fn timed_out(&mut self, line: &str) -> Result<()> {
+        self.timed_out = true;
+        self.failed = true;
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(Color::Red));
+        self.line(&spec, &line)
+    }
+
+    /// Writes the line reporting how long a task took.
+    pub(super) fn duration(&mut self, line: &str) -> Result<()> {
+        let mut spec = ColorSpec::new();
+        spec.set_dimmed(true);
+        self.line(&spec, &line)
+    }
+
+    /// Writes `text` in the given style on a line of its own.
+    fn line(&mut self, spec: &ColorSpec, text: &dyn Display) -> Result<()> {
+        // Lines written by the Sequencer are not held to the task's limits.
+        let mut limiter = self.limiter.take();
+        if let Some(limiter) = &mut limiter {
+            limiter.interrupt();
+        }
+        let result = self.unlimited_line(spec, text);
+        self.limiter = limiter;
+        result
+    }
+
+    fn unlimited_line(&mut self, spec: &ColorSpec, text: &dyn Display) -> Result<()> {
+        if !self.line_start {
+            self.write_all(b"\n")?;
+        }
+        self.indent()?;
+        self.set_color(spec)?;
+        write!(self, "{}", text)?;
+        self.reset()?;
+        self.write_all(b"\n")
+    }
+
+    /// Writes the number of times the previous line was repeated, if it was.
+    pub(super) fn end_repeats(&mut self) -> Result<()> {
+        if self.repeats == 0 {
+            return Ok(());
+        }
+        let line = self.messages.lock().repeated(self.repeats + 1);
+        self.repeats = 0;
+        let style = self.style.clone();
+        let mut spec = ColorSpec::new();
+        spec.set_dimmed(true);
+        self.line(&spec, &line)?;
+        if !style.is_none() {
+            self.set_color(&style)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the number of lines dropped for exceeding the task's limits, if
+    /// any were.
+    pub(super) fn end_limits(&mut self) -> Result<()> {
+        let dropped = match &mut self.limiter {
+            Some(limiter) => limiter.take_dropped(),
+            None => return Ok(()),
+        };
+        if dropped == 0 {
+            return Ok(());
+        }
+        let line = self.messages.lock().dropped(dropped);
+        let mut spec = ColorSpec::new();
+        spec.set_dimmed(true);
+        self.line(&spec, &line)
+    }
+
+    /// Writes the lines held back from the end of the task's output, after a
+    /// line saying how many before them were left out.
+    pub(super) fn end_elision(&mut self, unicode: bool) -> Result<()> {
+        let (omitted, tail) = match self.elision.take() {
+            Some(elision) => elision.finish(),
+            None => return Ok(()),
+        };
+        if omitted > 0 {
+            // The lines let through end where the held back ones begin.
+            let line_start = mem::replace(&mut self.line_start, true);
+            let line = self.messages.lock().omitted(omitted, unicode);
+            let mut spec = ColorSpec::new();
+            spec.set_dimmed(true);
+            self.line(&spec, &line)?;
+            self.line_start = line_start;
+        }
+        self.buffer.write_all(&tail)
+    }
+
+    pub(super) fn end_section(&mut self) -> Result<()> {
+        if !self.line_start {
+            self.write_all(b"\n")?;
+        }
+        self.depth = self.depth.saturating_sub(1);
+        Ok(())
+    }
+
+    /// Takes the complete lines written so far, each tagged with the task's
+    /// index in a color picked from `colors`, for printing in interleaved
+    /// mode. With `all`, also takes the rest as a line of its own.
+    pub(super) fn take_lines(&mut self, default: &Buffer, colors: &[Color], all: bool) -> Buffer {
+        let mut lines = self.blank(default);
+        let output = self.buffer.as_slice();
+        let end = if all {
+            output.len()
+        } else {
+            match output.iter().rposition(|&b| b == b'\n') {
+                Some(newline) => newline + 1,
+                None => return lines,
+            }
+        };
+
+        let mut spec = ColorSpec::new();
+        if !colors.is_empty() {
+            spec.set_fg(Some(colors[self.index % colors.len()]));
+        }
+        let spec = self.color_depth.adapt(&spec);
+        for line in output[..end].split_inclusive(|&b| b == b'\n') {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let _ = lines.set_color(&spec);
+            let _ = write!(lines, "task-{:02} |", self.index);
+            let _ = lines.reset();
+            let _ = lines.write_all(b" ");
+            let _ = lines.write_all(line);
+            let _ = lines.reset();
+            let _ = lines.write_all(b"\n");
+        }
+
+        let rest = output[end..].to_vec();
+        self.buffer.clear();
+        let _ = self.buffer.write_all(&rest);
+        lines
+    }
+
+    /// Begins a line: its timestamp if any, then indentation for the open
+    /// sections.
+    fn indent(&mut self) -> Result<()> {
+        let clock = self.clock;
+        let depth = self.depth;
+        let buffer = self.target();
+        if let Some(clock) = &clock {
+            clock.write(buffer)?;
+        }
+        for _ in 0..depth {
+            buffer.write_all(INDENT)?;
+        }
+        self.line_start = false;
+        Ok(())
+    }
+
+    /// Where the next byte goes: after the line held back while collapsing
+    /// progress, if there is one.
+    fn target(&mut self) -> &mut Buffer {
+        match &mut self.progress {
+            Some(progress) => progress,
+            None => &mut self.buffer,
+        }
+    }
+
+    /// Ends collapsing, putting the line held back, if any, into the buffered
+    /// output.
+    pub(super) fn commit_progress(&mut self) -> Result<()> {
+        match self.progress.take() {
+            Some(progress) => self.buffer.write_all(progress.as_slice()),
+            None => Ok(()),
+        }
+    }
+
+    /// Throws away the line held back, for being overwritten after a
+    /// carriage return.
+    fn overwrite_progress(&mut self) -> Result<()> {
+        if let Some(progress) = &mut self.progress {
+            progress.clear();
+        }
+        self.line_start = true;
+        if self.style.is_none() {
+            Ok(())
+        } else {
+            let style = self.style.clone();
+            self.set_color(&style)
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.bytes += buf.len();
+        if !self.collapse {
+            self.commit_progress()?;
+        }
+        let clean;
+        let buf = match &mut self.sanitizer {
+            Some(sanitizer) => {
+                let mut out = Vec::with_capacity(buf.len());
+                sanitizer.sanitize(buf, &mut out);
+                clean = out;
+                &clean
+            }
+            None => buf,
+        };
+        if self.buffer.supports_color() {
+            if buf.contains(&b'\x1b') && self.console {
+                self.write_translated(buf)
+            } else {
+                self.write_lines(buf)
+            }
+        } else {
+            let mut stripped = Vec::with_capacity(buf.len());
+            self.stripper.strip(buf, &mut stripped);
+            self.write_lines(&stripped)
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    /// Writes output colored by ANSI escape sequences, such as that of a
+    /// child process, to a buffer for the legacy Windows console by turning
+    /// the SGR sequences into colors set through the console API. Other
+    /// escape sequences would show up as garbage, so they are left out.
+    fn write_translated(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let esc = buf.iter().position(|&b| b == b'\x1b').unwrap_or(buf.len());
+            self.write_lines(&buf[..esc])?;
+            buf = &buf[esc..];
+            if buf.is_empty() {
+                break;
+            }
+            let len = truncate::escape_len(buf);
+            let sgr = buf[..len]
+                .strip_prefix(b"\x1b[")
+                .and_then(|sequence| sequence.strip_suffix(b"m"))
+                .and_then(|params| str::from_utf8(params).ok());
+            if let Some(params) = sgr {
+                let mut spec = self.style.clone();
+                ansi::apply_sgr(&mut spec, params);
+                if spec.is_none() {
+                    self.reset()?;
+                } else {
+                    self.set_color(&spec)?;
+                }
+            }
+            buf = &buf[len..];
+        }
+        Ok(())
+    }
+
+    fn write_lines(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let len = match buf.iter().position(|&b| b == b'\n') {
+                Some(newline) => newline + 1,
+                None => buf.len(),
+            };
+            let (line, rest) = buf.split_at(len);
+            if let Some(limiter) = &mut self.limiter {
+                if !limiter.admit(line) {
+                    buf = rest;
+                    continue;
+                }
+            }
+            if self.dedup {
+                let whole = self.line_start && line.ends_with(b"\n");
+                if whole && line == self.last_line.as_slice() {
+                    self.repeats += 1;
+                    buf = rest;
+                    continue;
+                }
+                self.end_repeats()?;
+                self.last_line.clear();
+                if whole {
+                    self.last_line.extend_from_slice(line);
+                }
+            }
+            let mut line = line;
+            if self.collapse {
+                // Only the text after the last carriage return that is not
+                // part of a line ending is kept, as a terminal would show.
+                let cr = line
+                    .windows(2)
+                    .rposition(|pair| pair[0] == b'\r' && pair[1] != b'\n');
+                let held_cr = !self.line_start
+                    && !line.starts_with(b"\n")
+                    && self.target().as_slice().ends_with(b"\r");
+                if let Some(cr) = cr {
+                    self.overwrite_progress()?;
+                    line = &line[cr + 1..];
+                } else if held_cr {
+                    self.overwrite_progress()?;
+                }
+                if self.progress.is_none() && !line.ends_with(b"\n") {
+                    let mut progress = self.buffer.clone();
+                    progress.clear();
+                    self.progress = Some(progress);
+                }
+            }
+            // Blank lines are left without trailing whitespace.
+            if self.line_start && line != b"\n" {
+                self.indent()?;
+            }
+            self.target().write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+            if let (Some(progress), true) = (&mut self.progress, self.line_start) {
+                self.buffer.write_all(progress.as_slice())?;
+                progress.clear();
+            }
+            buf = rest;
+        }
+        Ok(())
+    }
+}
+
+impl WriteColor for Output {
+    fn supports_color(&self) -> bool {
+        self.buffer.supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> Result<()> {
+        self.style = spec.clone();
+        let spec = self.color_depth.adapt(spec);
+        self.target().set_color(&spec)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.style = ColorSpec::new();
+        self.target().reset()
+    }
+
+    fn supports_hyperlinks(&self) -> bool {
+        self.buffer.supports_hyperlinks()
+    }
+
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> Result<()> {
+        self.target().set_hyperlink(link)
+    }
+}
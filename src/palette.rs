@@ -0,0 +1,170 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::env;
+use termcolor::{Color, ColorSpec};
+
+/// How many colors the destination of a Sequencer can display, which
+/// determines what becomes of colors that it cannot.
+///
+/// A task may use `Color::Rgb` and `Color::Ansi256` regardless. Where the
+/// destination has fewer colors, each one is replaced by the closest color it
+/// does have: 24-bit colors by one of the 256 colors or one of the 16 basic
+/// ones, and 256 colors by one of the 16.
+///
+/// Refer to [`Sequencer::color_depth`][crate::Sequencer::color_depth].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    /// 24-bit color, all of `Color::Rgb`.
+    TrueColor,
+    /// The 256 colors of `Color::Ansi256`.
+    Ansi256,
+    /// The 8 basic colors of termcolor's `Color`, in normal and intense
+    /// variants.
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Guesses the capability of the terminal from the environment, the same
+    /// way most other terminal programs do: `COLORTERM=truecolor` or
+    /// `COLORTERM=24bit` for 24-bit color, and a `TERM` that mentions
+    /// `256color` for 256 colors.
+    pub fn detect() -> Self {
+        if let Some(colorterm) = env::var_os("COLORTERM") {
+            if colorterm == "truecolor" || colorterm == "24bit" {
+                return ColorDepth::TrueColor;
+            }
+        }
+        match env::var("TERM") {
+            Ok(term) if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+
+    /// Replaces the colors in `spec` that cannot be displayed.
+    pub(crate) fn adapt(self, spec: &ColorSpec) -> Cow<'_, ColorSpec> {
+        if self == ColorDepth::TrueColor || !needs_adapting(self, spec) {
+            return Cow::Borrowed(spec);
+        }
+        let mut spec = spec.clone();
+        let (fg, fg_intense) = self.color(spec.fg().copied());
+        let (bg, _) = self.color(spec.bg().copied());
+        spec.set_fg(fg).set_bg(bg);
+        if fg_intense {
+            spec.set_intense(true);
+        }
+        Cow::Owned(spec)
+    }
+
+    /// Closest color that can be displayed, and whether it needs to be the
+    /// intense variant.
+    fn color(self, color: Option<Color>) -> (Option<Color>, bool) {
+        match (self, color) {
+            (ColorDepth::Ansi256, Some(Color::Rgb(r, g, b))) => {
+                (Some(Color::Ansi256(rgb_to_256(r, g, b))), false)
+            }
+            (ColorDepth::Ansi16, Some(Color::Rgb(r, g, b))) => {
+                let (color, intense) = rgb_to_16(r, g, b);
+                (Some(color), intense)
+            }
+            (ColorDepth::Ansi16, Some(Color::Ansi256(n))) => {
+                let (color, intense) = ansi256_to_16(n);
+                (Some(color), intense)
+            }
+            (_, color) => (color, false),
+        }
+    }
+}
+
+fn needs_adapting(depth: ColorDepth, spec: &ColorSpec) -> bool {
+    [spec.fg(), spec.bg()].iter().any(|color| match color {
+        Some(Color::Rgb(..)) => true,
+        Some(Color::Ansi256(_)) => depth == ColorDepth::Ansi16,
+        _ => false,
+    })
+}
+
+/// Levels of each component in the 6×6×6 color cube of the 256 colors.
+pub(crate) const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| -> u8 {
+        let mut nearest = 0;
+        for (i, &step) in (0..).zip(CUBE.iter()) {
+            if diff(c, step) < diff(c, CUBE[usize::from(nearest)]) {
+                nearest = i;
+            }
+        }
+        nearest
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+
+    // The gray ramp from 232 to 255 is finer than the cube's diagonal.
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray_step = (average.saturating_sub(3) / 10).min(23);
+    let gray_level = 8 + 10 * gray_step;
+    let gray = u8::try_from(gray_level).unwrap_or(u8::MAX);
+    let level_of = |i: u8| CUBE[usize::from(i)];
+    let cube_error = distance((r, g, b), (level_of(ri), level_of(gi), level_of(bi)));
+    let gray_error = distance((r, g, b), (gray, gray, gray));
+    if gray_error < cube_error {
+        232 + u8::try_from(gray_step).unwrap_or(23)
+    } else {
+        cube
+    }
+}
+
+fn diff(a: u8, b: u8) -> u8 {
+    a.max(b) - a.min(b)
+}
+
+/// Squared distance between two colors.
+fn distance(one: (u8, u8, u8), other: (u8, u8, u8)) -> u32 {
+    let square = |a: u8, b: u8| u32::from(diff(a, b)).pow(2);
+    square(one.0, other.0) + square(one.1, other.1) + square(one.2, other.2)
+}
+
+/// Basic colors in the order of their ANSI numbering.
+const BASIC: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+fn rgb_to_16(r: u8, g: u8, b: u8) -> (Color, bool) {
+    let max = r.max(g).max(b);
+    if max < 48 {
+        return (Color::Black, false);
+    }
+    // Components at least half as bright as the brightest one are lit.
+    let lit = |c: u8| usize::from(u16::from(c) * 2 >= u16::from(max));
+    let index = lit(r) | (lit(g) << 1) | (lit(b) << 2);
+    if index == 7 && max < 128 {
+        // Dark gray.
+        return (Color::Black, true);
+    }
+    (BASIC[index], max > 191)
+}
+
+fn ansi256_to_16(n: u8) -> (Color, bool) {
+    match n {
+        0..=7 => (BASIC[usize::from(n)], false),
+        8..=15 => (BASIC[usize::from(n - 8)], true),
+        16..=231 => {
+            let n = n - 16;
+            let r = CUBE[usize::from(n / 36)];
+            let g = CUBE[usize::from(n / 6 % 6)];
+            let b = CUBE[usize::from(n % 6)];
+            rgb_to_16(r, g, b)
+        }
+        232..=255 => {
+            let gray = 8 + 10 * (n - 232);
+            rgb_to_16(gray, gray, gray)
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::current;
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic;
+use std::sync::Once;
+
+/// What a panic said, and where.
+pub(crate) struct Panic {
+    pub(crate) message: String,
+    pub(crate) location: Option<String>,
+}
+
+thread_local! {
+    /// Panic of this thread that is yet to be written to its current task.
+    static PANIC: RefCell<Option<Panic>> = RefCell::new(None);
+}
+
+/// Whether panics abort instead of unwinding. Compilers too old to say are
+/// taken to unwind, which is the default.
+#[cfg(not(no_cfg_panic))]
+const ABORTS: bool = cfg!(panic = "abort");
+#[cfg(no_cfg_panic)]
+const ABORTS: bool = false;
+
+/// Installs a panic hook that, instead of printing the message of a panic
+/// straight to stderr, holds it for the current task of the panicking thread
+/// if that task captures panics. Panics anywhere else go to the hook that was
+/// installed before.
+pub(crate) fn install_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // There would be no chance to write it anywhere before the abort.
+            let captured = !ABORTS
+                && current::captures_panics()
+                && PANIC
+                    .try_with(|slot| {
+                        *slot.borrow_mut() = Some(Panic {
+                            message: message(info.payload()),
+                            location: info.location().map(ToString::to_string),
+                        });
+                    })
+                    .is_ok();
+            if !captured {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Takes the panic held for the current task, if any.
+pub(crate) fn take() -> Option<Panic> {
+    PANIC
+        .try_with(|slot| slot.borrow_mut().take())
+        .ok()
+        .flatten()
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_owned()
+    }
+}
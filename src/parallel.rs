@@ -0,0 +1,67 @@
+use crate::{Sequencer, Task};
+use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+
+/// Extension trait for running each item of a rayon parallel iterator as a
+/// task.
+///
+/// The task for each item is assigned an index according to the item's
+/// position in the iterator, so output is printed in the same order as the
+/// input regardless of which thread gets to which item first.
+///
+/// ```
+/// use oqueue::{ParallelIteratorExt, Sequencer};
+/// use rayon::prelude::*;
+///
+/// let oqueue = Sequencer::stderr();
+/// let files = vec!["build.rs", "src/lib.rs", "src/main.rs"];
+///
+/// files.par_iter().sequenced_for_each(&oqueue, |task, path| {
+///     writeln!(task, "checking {}", path);
+/// });
+/// ```
+pub trait ParallelIteratorExt: IndexedParallelIterator {
+    /// Calls `f` on each item in parallel, together with a task for writing
+    /// the output of that item. The task is [entered][Task::enter] as the
+    /// current task of the thread while `f` runs, and finished when `f`
+    /// returns.
+    ///
+    /// All the indices needed are taken up front, so this does not wait
+    /// for room under [`max_pending`][Sequencer::max_pending]. If `f` panics,
+    /// the items that never got a turn are left out of the output as if
+    /// [skipped][Sequencer::skip], so that tasks after them still get
+    /// printed.
+    ///
+    /// # Panics
+    ///
+    /// Panics on the items that go past the end set by
+    /// [`with_len`][Sequencer::with_len], like [`begin`][Sequencer::begin].
+    fn sequenced_for_each<F>(self, oqueue: &Sequencer, f: F)
+    where
+        F: Fn(Task, Self::Item) + Sync + Send,
+    {
+        let (first, len) = oqueue.reserve(self.len());
+        let _reserved = Reserved { oqueue, first, len };
+        self.enumerate().for_each(|(i, item)| {
+            assert!(i < len, "oqueue has already begun all of its tasks");
+            let task = oqueue.begin_reserved(first + i);
+            let _entered = task.enter();
+            f(task, item);
+        });
+    }
+}
+
+/// Hands back the reserved indices that did not get begun, once iteration is
+/// over one way or another.
+struct Reserved<'a> {
+    oqueue: &'a Sequencer,
+    first: usize,
+    len: usize,
+}
+
+impl Drop for Reserved<'_> {
+    fn drop(&mut self) {
+        self.oqueue.skip_reserved(self.first, self.len);
+    }
+}
+
+impl<I: IndexedParallelIterator> ParallelIteratorExt for I {}
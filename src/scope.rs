@@ -0,0 +1,77 @@
+use super::Sequencer;
+use crate::info::{Summary, Tally};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+
+/// Guard for a series of tasks of a Sequencer, created by
+/// [`Sequencer::scope`].
+///
+/// Every task begun while the scope exists must be finished by the time the
+/// scope ends.
+#[must_use = "the scope ends immediately if the guard is not held"]
+pub struct Scope<'a> {
+    sequencer: &'a Sequencer,
+    first: usize,
+    /// Tasks finished before the scope began.
+    before: Tally,
+    ended: bool,
+}
+
+impl<'a> Scope<'a> {
+    pub(super) fn new(sequencer: &'a Sequencer, first: usize, before: Tally) -> Self {
+        Scope {
+            sequencer,
+            first,
+            before,
+            ended: false,
+        }
+    }
+
+    /// Ends the scope, flushing all output and returning a summary of the
+    /// tasks that ran within it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any task begun within the scope is still running.
+    pub fn end(mut self) -> Summary {
+        self.ended = true;
+        self.finish()
+    }
+
+    fn finish(&self) -> Summary {
+        let last = self.sequencer.started.load(Ordering::Relaxed);
+
+        let mut inner = self.sequencer.inner().lock();
+        for index in self.first.max(inner.finished)..last {
+            if !inner.is_done(index) {
+                drop(inner);
+                panic!("oqueue scope ended while task {} is still running", index);
+            }
+        }
+
+        let emission = if inner.batch {
+            Some(inner.detach())
+        } else {
+            None
+        };
+        let summary = Summary::new(last - self.first, &self.before, &inner.tally);
+        let sink = Arc::clone(&inner.sink);
+        drop(inner);
+
+        if let Some(emission) = emission {
+            let _ = emission.emit();
+        }
+        let _ = sink.lock().sync();
+
+        summary
+    }
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        if !self.ended && !thread::panicking() {
+            self.finish();
+        }
+    }
+}
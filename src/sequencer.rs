@@ -2,17 +2,62 @@
 // https://github.com/rust-lang/rust-clippy/issues/3071
 #![allow(clippy::redundant_closure)]
 
+#[path = "builder.rs"]
+mod builder;
+#[path = "output.rs"]
+mod output;
+#[path = "scope.rs"]
+mod scope;
 #[path = "task.rs"]
 mod task;
 
-use crate::sync::Mutex;
-use std::collections::VecDeque;
+use crate::ansi::{Sanitizer, Stripper};
+use crate::archive::Archive;
+use crate::capture::Capture;
+use crate::events::Events;
+use crate::folding::Folding;
+use crate::html::Html;
+use crate::info::{PendingTask, QueueState, Stats, Summary, Tally, TaskInfo};
+use crate::json::Finished;
+use crate::junit::JUnit;
+use crate::limit::{Elision, Limiter};
+use crate::messages::{English, Messages};
+#[cfg(feature = "opentelemetry")]
+use crate::otel::TaskSpan;
+use crate::palette::ColorDepth;
+#[cfg(feature = "indicatif")]
+use crate::sink::Progress;
+use crate::sink::{Ansi, Cast, Footer, Queue, Sink, Stream, Tee, Writer};
+use crate::spill::Spill;
+use crate::sync::{Mutex, OnceCell};
+use crate::tap::Tap;
+#[cfg(feature = "metrics")]
+use crate::telemetry::Metrics;
+use crate::theme::{self, Theme};
+use crate::timestamp::{Clock, Timestamps};
+use crate::truncate;
+use crate::watchdog::Ticker;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::iter;
+use std::mem;
+use std::path::Path;
+#[cfg(not(no_scoped_threads))]
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use termcolor::ColorChoice::Auto;
-use termcolor::{Buffer, BufferWriter, StandardStream};
+use std::sync::{Arc, Condvar, MutexGuard, PoisonError};
+#[cfg(any(feature = "interrupt", feature = "exit"))]
+use std::sync::{Once, Weak};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+use termcolor::ColorChoice::{self, Auto};
+use termcolor::{Buffer, Color, ColorSpec, WriteColor};
 
-pub use self::task::Task;
+pub use self::builder::SequencerBuilder;
+pub use self::scope::Scope;
+pub(crate) use self::task::Handle;
+pub use self::task::{Atomic, Section, Task, TaskWriter};
 
 /// Synchronization mechanism for performing non-interleaved output from
 /// concurrent tasks.
@@ -240,84 +285,3981 @@ pub use self::task::Task;
 ///
 /// <br>
 pub struct Sequencer {
-    inner: Arc<Mutex<Inner>>,
+    /// Thread keeping an eye on tasks that have gone quiet. Dropped ahead of
+    /// `inner`, so that the last reference to Inner is never the thread's.
+    ticker: Option<Ticker>,
+    /// Set up on first use for Sequencers made in a const context.
+    inner: OnceCell<Arc<Mutex<Inner>>>,
+    lazy: Lazy,
     /// Index of next started task.
     started: AtomicUsize,
+    /// Number of tasks begun by `begin_after`, whose indices count down from
+    /// `usize::MAX` so as to stay clear of those of all other tasks.
+    children: AtomicUsize,
+    /// Most tasks allowed to be begun but not yet printed.
+    max_pending: Option<usize>,
+    /// Whether there is a status footer to keep up to date.
+    footer: bool,
+    /// Index after the last task, if the number of tasks is known.
+    end: Option<usize>,
+    /// Called with the index of every task that is begun.
+    on_start: Vec<Arc<dyn Fn(usize) + Send + Sync>>,
 }
 
 #[cfg(test)]
 struct _Test
 where
-    Sequencer: Send + Sync;
+    Sequencer: Send + Sync,
+    Task: Send + Sync;
 
+type SharedSink = Arc<Mutex<Box<dyn Sink>>>;
+
+type Hook = Arc<dyn Fn(&TaskInfo) + Send + Sync>;
+
+type SharedMessages = Arc<Mutex<Box<dyn Messages>>>;
+
+/// Callbacks observing tasks whose output is being sequenced.
+#[derive(Default)]
+struct Hooks {
+    /// Called when a task finishes, whether or not printed yet.
+    complete: Vec<Hook>,
+    /// Called once a finished task's output has all been printed.
+    flushed: Vec<Hook>,
+}
+
+/// Destination of a Sequencer whose state has not been set up yet.
+enum Lazy {
+    Stdout,
+    Stderr,
+    Ready,
+}
+
+#[allow(clippy::struct_excessive_bools)]
 struct Inner {
-    stream: StandardStream,
-    writer: BufferWriter,
+    /// Locked separately from Inner so that printing can happen without
+    /// holding up tasks that are writing to their buffers.
+    sink: SharedSink,
+    /// Alternative destinations for the output of particular tasks.
+    routes: Vec<Route>,
+    /// Empty buffer of the kind the sink prints.
+    blank: Buffer,
+    /// Destination of output written through `Task::err`, if different from
+    /// `sink`.
+    err_sink: Option<SharedSink>,
+    /// Empty buffer of the kind the error sink prints.
+    err_blank: Buffer,
+    /// Whether some thread is in the middle of printing buffers detached from
+    /// the queue. While this is set, output of the head task is buffered too,
+    /// to keep it from overtaking the output being printed.
+    emitting: bool,
+    /// Output printed as soon as it is written, such as that of the task
+    /// printing in real time or the lines of interleaved tasks, taken under
+    /// the lock to be printed by `emit_ready` ahead of everything else.
+    streamed: Vec<Detached>,
+    /// Whether all output is held back until the end of the run.
+    batch: bool,
+    /// Whether each task's output is printed only once the task is finished.
+    whole_tasks: bool,
+    /// Whether output of tasks that do not fail is thrown away.
+    quiet: bool,
+    /// Format in which tasks are reported once finished, if not as is.
+    report: Option<Report>,
+    /// Outcomes of the tasks finished so far, for the JUnit report.
+    junit: Option<JUnit>,
+    /// Outcomes of the tasks finished so far, for the HTML page.
+    html: Option<Html>,
+    /// Where to keep a log file of each task.
+    archive: Option<Archive>,
+    /// Where to describe what each task is doing, for other programs.
+    events: Option<Events>,
+    /// Whether tasks are printed in the order they finish, instead of by
+    /// index.
+    completion_order: bool,
+    /// In completion order, the task currently printing in real time.
+    claimed: Option<usize>,
+    /// Print complete lines of every task as soon as they are written, tagged
+    /// with the task's index, instead of sequencing whole tasks.
+    interleaved: bool,
+    /// Colors of the tags of interleaved lines, cycled through by index.
+    prefix_colors: Arc<[Color]>,
+    /// Markers to put around the output of each task for a CI system.
+    folding: Option<Folding>,
+    /// In completion order, finished tasks in the order they finished, not
+    /// yet printed.
+    completed: VecDeque<usize>,
+    /// Source of all text printed by the Sequencer on its own behalf.
+    messages: SharedMessages,
+    /// Styles of diagnostics written by tasks.
+    theme: Theme,
+    /// Whether status markers may use characters beyond ASCII.
+    unicode: bool,
     /// Number of tasks popped from queue.
     finished: usize,
+    /// Notified whenever tasks are popped from the queue.
+    popped: Arc<Condvar>,
     pending: VecDeque<Output>,
+    /// Parent of each task begun by `begin_after` that is not yet printed.
+    parents: HashMap<usize, usize>,
+    /// Index of the first task.
+    first: usize,
+    /// Outcomes of the tasks finished so far.
+    tally: Tally,
+    /// Number of bytes written to all tasks so far.
+    written: u64,
+    /// Most tasks that have been begun but not printed at any one time.
+    peak_pending: usize,
+    /// Where to report what is going on, if anywhere.
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    /// Whether each task is covered by an OpenTelemetry span.
+    #[cfg(feature = "opentelemetry")]
+    spans: bool,
+    /// Whether to print a line summarizing the run at the end.
+    summary: bool,
+    /// Amount of buffered output above which it gets moved to disk.
+    spill_limit: Option<usize>,
+    /// Amount of buffered output of each task past which the oldest is thrown
+    /// away.
+    tail: Option<usize>,
+    /// Amount of output held in memory by tasks other than the one printing
+    /// in real time.
+    buffered: usize,
+    /// Most output that has been held in memory at once.
+    peak_buffered: usize,
+    /// Whether to print a line at the end of the run saying `peak_buffered`.
+    print_peak: bool,
+    hooks: Hooks,
+    /// First error encountered while printing, since it was last reported by
+    /// `wait`.
+    error: Option<io::Error>,
+    /// Whether the output was closed by whoever was reading it, so printing
+    /// anything more is pointless.
+    closed: bool,
+    /// Whether tasks begun from now on capture panics.
+    capture_panics: bool,
+    /// Number of failed tasks after which no more get handed out.
+    fail_fast: Option<usize>,
+    /// How long the task printing next may go without output before a
+    /// notice that it is still running.
+    watchdog: Option<Duration>,
+    /// The task printing next as of the last tick of the watchdog.
+    watched: Option<Watched>,
+    /// How long to go without printing anything before printing a line just
+    /// to show that the program is alive.
+    keepalive: Option<Duration>,
+    /// When output was last printed.
+    printed: Instant,
+    /// Longest a task may run before being marked failed.
+    timeout: Option<Duration>,
+    /// Whether the run was called off by `cancel_all`.
+    cancelled: bool,
+    /// Text of the status footer, if there is one.
+    footer: Option<Arc<Mutex<String>>>,
+    /// Number of tasks begun, counted only for the status footer.
+    begun: usize,
+    /// Task most recently printing in real time, if still running.
+    live: Option<usize>,
+    /// Whether tasks with a title end with a line reporting their outcome.
+    title_footers: bool,
+    /// Whether every task ends with a line reporting how long it took.
+    durations: bool,
+    /// Source of the timestamp at the start of each line, if any.
+    clock: Option<Clock>,
+    /// Colors that the destination can display.
+    color_depth: ColorDepth,
+    /// Whether consecutive identical lines of a task are collapsed.
+    dedup: bool,
+    /// Whether lines overwritten after a carriage return are left out of
+    /// buffered output.
+    collapse_progress: bool,
+    /// Whether escape sequences other than color are removed from output.
+    sanitize: bool,
+    /// Most lines that a task may write.
+    max_lines: Option<usize>,
+    /// Most bytes per second that a task may write.
+    max_rate: Option<usize>,
+    /// Number of lines at the beginning and at the end printed of a task
+    /// whose output is longer than that.
+    elide: Option<(usize, usize)>,
+}
+
+struct Route {
+    predicate: Box<dyn Fn(&TaskInfo) -> bool + Send>,
+    sink: SharedSink,
+}
+
+/// Whether a task's output has been surrounded by the markers of a fold.
+enum Fold {
+    Unopened,
+    /// Begun, with the given title, which some CI systems need repeated at
+    /// the end.
+    Open(Folding, String),
+    Closed,
+}
+
+/// Format in which finished tasks are reported, in place of their output.
+enum Report {
+    Tap(Tap),
+    Json,
+}
+
+/// Part of a task's output that went to one of its two streams.
+struct Segment {
+    err: bool,
+    buffer: Buffer,
 }
 
-struct Output {
+/// Output taken out of the queue, ready to be printed.
+struct Detached {
+    sink: SharedSink,
+    /// Printed ahead of `buffer`.
+    spill: Option<Spill>,
     buffer: Buffer,
+}
+
+/// Everything to be done after releasing the lock, in order: printing, then
+/// notifying hooks about the tasks that got printed in their entirety.
+#[must_use]
+struct Emission {
+    detached: Vec<Detached>,
+    flushed: Vec<TaskInfo>,
+    hooks: Vec<Hook>,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct Output {
+    index: usize,
+    buffer: Buffer,
+    /// Per-task override of the Sequencer's color setting.
+    color: ColorChoice,
+    /// Whether the next byte written begins a new line.
+    line_start: bool,
+    /// Number of sections currently open.
+    depth: usize,
+    /// Style most recently set, restored by the guards of `Task::styled`.
+    style: ColorSpec,
+    /// Number of atomic blocks currently open. While there are any, output is
+    /// held back even if this is the task printing in real time.
+    atomic: usize,
+    /// Whether `buffer` is for the legacy Windows console, so escape
+    /// sequences written to it need to be turned into console colors.
+    console: bool,
+    /// Removes pre-rendered color from output that is not supposed to be
+    /// colored.
+    stripper: Stripper,
+    /// Removes escape sequences other than color from the task's output, if
+    /// it is not trusted.
+    sanitizer: Option<Sanitizer>,
+    /// Whether `buffer` holds output for the error stream, written through
+    /// `Task::err`.
+    err: bool,
+    /// Output written before the task last switched between its output and
+    /// error streams, oldest first.
+    earlier: Vec<Segment>,
+    /// Title given by `set_title`, if any.
+    title: Option<String>,
+    /// Copy of all output written to the task, for the JUnit report, the
+    /// HTML page and the archive.
+    transcript: Vec<u8>,
+    /// Progress of the fold around the task's output in a CI log.
+    fold: Fold,
+    /// Source of the timestamp at the start of each line, if any.
+    clock: Option<Clock>,
+    /// Colors that the destination can display.
+    color_depth: ColorDepth,
+    /// Source of the text written by the Sequencer into the task's output.
+    messages: SharedMessages,
+    /// Whether a line written more than once in a row is written only once.
+    dedup: bool,
+    /// Most recent complete line, as written by the task, for comparing the
+    /// next line against.
+    last_line: Vec<u8>,
+    /// Number of times in a row that `last_line` was written again.
+    repeats: usize,
+    /// Whether lines overwritten after a carriage return are left out of the
+    /// buffered output, as for output not printed in real time.
+    collapse: bool,
+    /// Line in progress held back while collapsing, until it is complete.
+    progress: Option<Buffer>,
+    /// Enforces the limits on how much the task may write, if any.
+    limiter: Option<Limiter>,
+    /// Lines of the middle of the task's output being left out, if they are.
+    elision: Option<Elision>,
+    /// Whether this only holds the place in sequence of a task that was
+    /// begun by `begin_after` and lives among the children of another.
+    placeholder: bool,
+    /// Whether a task has been begun with this index.
+    claimed: bool,
+    /// Whether the task was cancelled, so anything it writes is thrown away.
+    discarded: bool,
+    /// Whether the task was marked as failed.
+    failed: bool,
+    /// Oldest part of the task's output, moved out of memory.
+    spill: Option<Spill>,
+    /// Number of bytes of the task's output thrown away to keep only the
+    /// tail.
+    trimmed: usize,
+    /// Length of the line at the front of the buffered output saying how much
+    /// was thrown away.
+    notice: usize,
+    /// Whether the task ran for longer than the timeout.
+    timed_out: bool,
+    /// Number of bytes written to the task.
+    bytes: usize,
+    /// When the task was begun.
+    begun: Instant,
+    /// Time from when the task was begun until it finished, once done.
+    elapsed: Duration,
     done: bool,
+    /// Thread that finished the task, once done.
+    thread: ThreadId,
+    /// Tasks to be printed after this one, ahead of the next task in sequence.
+    children: VecDeque<Output>,
+    /// Span covering the task while it runs.
+    #[cfg(feature = "opentelemetry")]
+    span: Option<TaskSpan>,
 }
 
 impl Sequencer {
-    fn new(stream: StandardStream, writer: BufferWriter) -> Self {
+    pub(crate) fn new(sink: Box<dyn Sink>) -> Self {
+        Sequencer {
+            ticker: None,
+            inner: OnceCell::with_value(Arc::new(Mutex::new(Inner::new(sink)))),
+            lazy: Lazy::Ready,
+            started: AtomicUsize::new(0),
+            children: AtomicUsize::new(0),
+            max_pending: None,
+            footer: false,
+            end: None,
+            on_start: Vec::new(),
+        }
+    }
+
+    const fn lazy(lazy: Lazy) -> Self {
         Sequencer {
-            inner: Arc::new(Mutex::new(Inner {
-                stream,
-                writer,
-                finished: 0,
-                pending: VecDeque::new(),
-            })),
+            ticker: None,
+            inner: OnceCell::new(),
+            lazy,
             started: AtomicUsize::new(0),
+            children: AtomicUsize::new(0),
+            max_pending: None,
+            footer: false,
+            end: None,
+            on_start: Vec::new(),
+        }
+    }
+
+    fn inner(&self) -> &Arc<Mutex<Inner>> {
+        self.inner.get_or_init(|| {
+            // Statics are never dropped, so there would be no last chance to
+            // write out output accumulated by a staging stream.
+            let stream = match self.lazy {
+                Lazy::Stdout => Stream::stdout(Auto),
+                Lazy::Stderr => Stream::stderr(Auto),
+                Lazy::Ready => unreachable!(),
+            };
+            let mut inner = Inner::new(Box::new(stream.unstaged()));
+            inner.folding = Folding::detect();
+            inner.whole_tasks = !inner.sink.lock().is_terminal();
+            if let Lazy::Stdout = self.lazy {
+                inner.set_err_sink(Box::new(Stream::stderr(Auto).unstaged()));
+            }
+            Arc::new(Mutex::new(inner))
+        })
+    }
+
+    /// Sends output written through [`Task::err`] to the given sink instead
+    /// of the main one.
+    fn with_err_sink(self, sink: Box<dyn Sink>) -> Self {
+        self.inner().lock().set_err_sink(sink);
+        self
+    }
+
+    /// Turns on the folding of the CI system being run under, if any. Only
+    /// done for stdout and stderr, as opposed to arbitrary writers which are
+    /// unlikely to be going to the CI log.
+    fn detect_folding(self) -> Self {
+        self.inner().lock().folding = Folding::detect();
+        self
+    }
+
+    /// Prints the output of each task in one piece once it is finished, if
+    /// the output is not going to a terminal. Only done for stdout and stderr;
+    /// arbitrary writers keep printing one task in real time.
+    fn detect_whole_tasks(self) -> Self {
+        {
+            let inner = &mut *self.inner().lock();
+            inner.whole_tasks = !inner.sink.lock().is_terminal();
         }
+        self
     }
 
     /// Makes a sequencer whose output goes to stdout.
+    ///
+    /// Output is colored only if stdout is a terminal, unless overridden by
+    /// the environment: `CLICOLOR_FORCE` set to anything other than `0` turns
+    /// color on even if stdout is redirected, for CI systems that render it,
+    /// while `NO_COLOR`, `CLICOLOR=0` and `TERM=dumb` turn it off.
+    ///
+    /// On Windows, the console is switched to understanding escape sequences
+    /// where possible. On an older console that cannot be, colors are set
+    /// through the console API instead, including colors that tasks write as
+    /// escape sequences, such as the output of a child process.
     pub fn stdout() -> Self {
-        Self::new(StandardStream::stdout(Auto), BufferWriter::stdout(Auto))
+        Self::stdout_with_color(Auto)
     }
 
     /// Makes a sequencer whose output goes to stderr.
+    ///
+    /// Color is detected in the same way as for
+    /// [`stdout`][Sequencer::stdout].
     pub fn stderr() -> Self {
-        Self::new(StandardStream::stderr(Auto), BufferWriter::stderr(Auto))
+        Self::stderr_with_color(Auto)
     }
 
-    /// Begins the next available task.
+    /// Makes a sequencer whose output goes to stdout, with color controlled by
+    /// `choice` rather than detected automatically.
     ///
-    /// The caller may figure out what work to perform based on the index of
-    /// this task available in `task.index`, or by acquiring work from a
-    /// synchronized queue that is shared across workers.
+    /// This is what a command line flag like `--color=always` would turn into.
     ///
-    /// This call does not block.
-    pub fn begin(&self) -> Task {
-        let index = self.started.fetch_add(1, Ordering::Relaxed);
-        Task::new(index, self.inner.clone())
+    /// ```
+    /// use oqueue::{ColorChoice, Sequencer};
+    ///
+    /// # let flag = "auto";
+    /// let choice = match flag {
+    ///     "always" => ColorChoice::Always,
+    ///     "never" => ColorChoice::Never,
+    ///     _ => ColorChoice::Auto,
+    /// };
+    /// let oqueue = Sequencer::stdout_with_color(choice);
+    /// ```
+    pub fn stdout_with_color(choice: ColorChoice) -> Self {
+        let (stdout, stderr) = Stream::pair(choice);
+        Self::new(Box::new(stdout))
+            .with_err_sink(Box::new(stderr))
+            .detect_folding()
+            .detect_whole_tasks()
     }
-}
 
-impl Inner {
-    fn get(&mut self, index: usize) -> &mut Output {
-        assert!(index >= self.finished);
-        let offset = index - self.finished;
-
-        if offset >= self.pending.len() {
-            let writer = &self.writer;
-            self.pending.resize_with(offset + 1, || Output {
-                buffer: writer.buffer(),
-                done: false,
-            });
+    /// Makes a sequencer whose output goes to stderr, with color controlled by
+    /// `choice` rather than detected automatically.
+    pub fn stderr_with_color(choice: ColorChoice) -> Self {
+        Self::new(Box::new(Stream::stderr(choice)))
+            .detect_folding()
+            .detect_whole_tasks()
+    }
+
+    /// Makes a sequencer whose output goes to the given writer, such as a
+    /// file, a pipe, or an in-process aggregator.
+    ///
+    /// Output is written without color. A particular task can opt in to ANSI
+    /// color codes using [`Task::color_choice`] with [`ColorChoice::Always`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::io::{self, Write};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct Collect(Arc<Mutex<Vec<u8>>>);
+    ///
+    /// impl Write for Collect {
+    ///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    ///         self.0.lock().unwrap().write(buf)
+    ///     }
+    ///
+    ///     fn flush(&mut self) -> io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let collect = Collect::default();
+    /// let oqueue = Sequencer::with_writer(collect.clone());
+    ///
+    /// let first = oqueue.begin();
+    /// let second = oqueue.begin();
+    /// writeln!(second, "second");
+    /// writeln!(first, "first");
+    /// drop(second);
+    /// drop(first);
+    ///
+    /// assert_eq!(*collect.0.lock().unwrap(), b"first\nsecond\n");
+    /// ```
+    pub fn with_writer<W>(writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        Self::new(Box::new(Writer::new(Box::new(writer))))
+    }
+
+    /// Makes a sequencer whose output is collected in memory, for tests.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// rayon::scope(|s| {
+    ///     for _ in 0..4 {
+    ///         s.spawn(|_| loop {
+    ///             let task = oqueue.begin();
+    ///             if task.index >= 3 {
+    ///                 return;
+    ///             }
+    ///             writeln!(task, "task #{}", task.index);
+    ///         });
+    ///     }
+    /// });
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(capture.string(), "task #0\ntask #1\ntask #2\n");
+    /// ```
+    pub fn capture() -> (Self, Capture) {
+        let capture = Capture::new();
+        (Self::with_writer(capture.clone()), capture)
+    }
+
+    /// Makes a sequencer whose output goes to a newly created file, replacing
+    /// the file's previous contents if it already exists.
+    ///
+    /// Output is written without color, and in large chunks. It has all been
+    /// written by the time the Sequencer is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from creating the file, for example if the directory
+    /// it would go in does not exist.
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::file("build.log")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::with_writer(BufWriter::new(file)))
+    }
+
+    /// Makes a sequencer whose output is added to the end of a file, creating
+    /// it if it does not exist yet.
+    ///
+    /// Refer to [`file`][Sequencer::file].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from opening the file.
+    pub fn file_append<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::with_writer(BufWriter::new(file)))
+    }
+
+    /// Makes a sequencer whose output goes to stdout, in a form that can be
+    /// stored in a `static`.
+    ///
+    /// Nothing is set up until the sequencer is first used. Because statics
+    /// are never dropped, output is written to stdout as soon as it is ready,
+    /// rather than accumulated into larger writes when stdout is not a
+    /// terminal.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::thread;
+    ///
+    /// static OQUEUE: Sequencer = Sequencer::lazy_stdout();
+    ///
+    /// fn main() {
+    ///     let workers: Vec<_> = (0..4)
+    ///         .map(|_| {
+    ///             thread::spawn(|| loop {
+    ///                 let task = OQUEUE.begin();
+    ///                 if task.index >= 10 {
+    ///                     return;
+    ///                 }
+    ///                 writeln!(task, "task #{}", task.index);
+    ///             })
+    ///         })
+    ///         .collect();
+    ///
+    ///     for worker in workers {
+    ///         worker.join().unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub const fn lazy_stdout() -> Self {
+        Self::lazy(Lazy::Stdout)
+    }
+
+    /// Makes a sequencer whose output goes to stderr, in a form that can be
+    /// stored in a `static`.
+    ///
+    /// Refer to [`lazy_stdout`][Sequencer::lazy_stdout].
+    pub const fn lazy_stderr() -> Self {
+        Self::lazy(Lazy::Stderr)
+    }
+
+    /// Moves all printing onto a background thread, so that writing to a task
+    /// does not stall on a slow sink such as a network socket or a FUSE
+    /// filesystem.
+    ///
+    /// Output is handed off to the background thread through a queue holding
+    /// up to `capacity` chunks, and printed from there in the same order as
+    /// it would otherwise have been printed. Tasks only block in the event
+    /// that the queue is full. Everything queued is printed before the last
+    /// of the Sequencer and its tasks is dropped.
+    #[must_use]
+    pub fn nonblocking(self, capacity: usize) -> Self {
+        self.queue(Some(capacity))
+    }
+
+    /// Performs all printing on a dedicated writer thread fed by an unbounded
+    /// channel.
+    ///
+    /// Unlike [`nonblocking`][Sequencer::nonblocking], tasks never wait for
+    /// the writer thread: writing to a task only ever appends to memory, no
+    /// matter how far behind the terminal is. This decouples the throughput
+    /// of the workers from terminal latency, such as over a slow SSH session,
+    /// at the cost of unbounded memory use if output is produced faster than
+    /// it can be printed.
+    ///
+    /// Finished tasks' output is handed to the writer thread without being
+    /// copied, and a task's `flush` only asks the writer thread to flush
+    /// rather than waiting for it. Call [`wait`][Sequencer::wait] to block
+    /// until everything has been written.
+    #[must_use]
+    pub fn writer_thread(self) -> Self {
+        self.queue(None)
+    }
+
+    /// Buffers the output of every task, including the one that would
+    /// otherwise be printed in real time, and prints all of it in order at the
+    /// end of the run once the Sequencer and all of its tasks have been
+    /// dropped.
+    ///
+    /// This maximizes throughput when nobody is watching the output live, for
+    /// example when it is collected into a CI log. Each task's output is
+    /// printed with a single write.
+    #[must_use]
+    pub fn batch(self) -> Self {
+        self.inner().lock().batch = true;
+        self
+    }
+
+    /// Controls whether the output of each task is held back until the task
+    /// is finished, then printed in one piece, instead of the output of one
+    /// task at a time being printed in real time.
+    ///
+    /// Output is still printed in order, and as soon as each task's turn
+    /// comes, unlike with [`batch`][Sequencer::batch]. When nobody is watching
+    /// the output live this saves the locking and the many small writes of
+    /// printing in real time. It is turned on by default for a Sequencer
+    /// printing to stdout or stderr when that is not a terminal, such as when
+    /// redirected to a file; pass `false` to always print in real time.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// // Watch progress live even from a pipe, for example through `tee`.
+    /// let oqueue = Sequencer::stderr().whole_tasks(false);
+    /// ```
+    #[must_use]
+    pub fn whole_tasks(self, enabled: bool) -> Self {
+        self.inner().lock().whole_tasks = enabled;
+        self
+    }
+
+    /// Writes the message of a panic into the output of the task that was
+    /// running, and marks that task failed, instead of letting the message go
+    /// to stderr on its own, where it would appear out of context.
+    ///
+    /// This applies to panics on a thread while a task of this Sequencer is
+    /// its [current task][Task::enter], which includes the tasks run by
+    /// [`task`][Sequencer::task], [`map_ordered`][Sequencer::map_ordered] and
+    /// [`#[oqueue::task]`][crate::task]. Other panics are reported by whatever
+    /// panic hook was installed before the first call of this method. The
+    /// wording can be changed through [`Messages::panicked`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::panic::{self, AssertUnwindSafe};
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.capture_panics();
+    ///
+    /// let result = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     oqueue.task(|task| {
+    ///         writeln!(task, "about to fail");
+    ///         panic!("something went wrong");
+    ///     })
+    /// }));
+    /// assert!(result.is_err());
+    /// drop(oqueue);
+    ///
+    /// // about to fail
+    /// // panicked at src/main.rs:12:9:
+    /// // something went wrong
+    /// let output = capture.string();
+    /// assert!(output.starts_with("about to fail\npanicked at "));
+    /// assert!(output.ends_with(":\nsomething went wrong\n"));
+    /// ```
+    ///
+    /// [`Messages::panicked`]: crate::Messages::panicked
+    #[must_use]
+    pub fn capture_panics(self) -> Self {
+        crate::panic::install_hook();
+        self.inner().lock().capture_panics = true;
+        self
+    }
+
+    /// Prints what every task has written so far when the user presses
+    /// Ctrl-C, instead of losing the output of tasks that were done but not
+    /// yet printed.
+    ///
+    /// The output is printed in order, including the partial output of tasks
+    /// still running, after which nothing more is printed and the process is
+    /// ended as it would have been otherwise. This replaces any handler for
+    /// SIGINT installed earlier.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stdout().flush_on_interrupt();
+    /// ```
+    #[cfg(feature = "interrupt")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "interrupt")))]
+    #[must_use]
+    pub fn flush_on_interrupt(self) -> Self {
+        static INSTALLED: Once = Once::new();
+        INSTALLED.call_once(|| {
+            let _ = crate::interrupt::install();
+        });
+        register(&INTERRUPTIBLE, self.inner());
+        self
+    }
+
+    /// Prints what every task has written so far when the process exits by
+    /// [`std::process::exit`], instead of losing the output of tasks that
+    /// were done but not yet printed.
+    ///
+    /// This is for when some other code, such as a library or a task that
+    /// hit a fatal error, ends the process without giving the Sequencer the
+    /// chance to be dropped. It also covers a Sequencer in a static, which is
+    /// never dropped, when `main` returns. The output is printed in order,
+    /// including the partial output of tasks still running, after which
+    /// nothing more is printed. It relies on the C runtime's `atexit`, which
+    /// does not run when the process is aborted or killed by a signal.
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stdout().flush_on_exit();
+    /// let task = oqueue.begin();
+    /// writeln!(task, "printed even though the task is never finished");
+    /// std::mem::forget(task);
+    /// std::process::exit(0);
+    /// ```
+    #[cfg(feature = "exit")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "exit")))]
+    #[must_use]
+    pub fn flush_on_exit(self) -> Self {
+        static INSTALLED: Once = Once::new();
+        INSTALLED.call_once(crate::exit::install);
+        register(&EXITING, self.inner());
+        self
+    }
+
+    /// Prints the output of tasks in the order they finish, rather than in
+    /// order of their index.
+    ///
+    /// Output of different tasks is still never interleaved. The first task to
+    /// write while no other task is printing claims the terminal and prints in
+    /// real time until it finishes. Meanwhile tasks that finish are printed as
+    /// soon as the claim is released, in the order they finished, and running
+    /// ones keep buffering until they next get a chance to claim it.
+    ///
+    /// This minimizes latency for workloads where ordering between tasks is
+    /// not meaningful. With this mode, [`begin_after`][Sequencer::begin_after]
+    /// is the same as `begin`.
+    #[must_use]
+    pub fn completion_order(self) -> Self {
+        self.inner().lock().completion_order = true;
+        self
+    }
+
+    /// Prints every task's output live, interleaved a line at a time, with
+    /// each line tagged by the index of the task that wrote it.
+    ///
+    /// ```console
+    /// task-00 | compiling foo
+    /// task-01 | compiling bar
+    /// task-00 | finished foo
+    /// ```
+    ///
+    /// This gives up the ordering between tasks in exchange for seeing all of
+    /// them make progress at once, in the style of `docker-compose`. A line
+    /// is printed once its newline is written; whatever is left without one
+    /// when the task finishes is printed as a line of its own. Color set by a
+    /// task lasts until the end of the line, so that it never carries over
+    /// into the lines of other tasks. This mode takes precedence over
+    /// [`batch`][Sequencer::batch], [`route`][Sequencer::route] and
+    /// [`quiet_unless_failed`][Sequencer::quiet_unless_failed], which are
+    /// about entire tasks.
+    #[must_use]
+    pub fn interleaved(self) -> Self {
+        self.inner().lock().interleaved = true;
+        self
+    }
+
+    /// Surrounds the output of each task with markers that let a CI system
+    /// collapse it, or with `None`, leaves output as is.
+    ///
+    /// By default, a Sequencer whose output goes to stdout or stderr uses the
+    /// folding of the CI system it is being run under, if any, as determined
+    /// by [`Folding::detect`]. Each fold is titled with the task's
+    /// [title][Task::set_title] or else by [`Messages::fold_title`], and is
+    /// only made for tasks that write output. This has no effect in
+    /// [`interleaved`][Sequencer::interleaved] mode.
+    ///
+    /// [`Messages::fold_title`]: crate::Messages::fold_title
+    #[must_use]
+    pub fn folding(self, folding: Option<Folding>) -> Self {
+        self.inner().lock().folding = folding;
+        self
+    }
+
+    /// Replaces the colors of the `task-00 |` tags of
+    /// [`interleaved`][Sequencer::interleaved] output.
+    ///
+    /// Each task's tag gets one of the colors, going around the list in order
+    /// of index, so a task keeps the same color throughout. By default the
+    /// list is cyan, yellow, green, magenta, blue and red. With an empty list
+    /// the tags are left uncolored.
+    ///
+    /// ```
+    /// use oqueue::{Color, Sequencer};
+    ///
+    /// let oqueue = Sequencer::stderr()
+    ///     .interleaved()
+    ///     .prefix_colors(vec![Color::Ansi256(208), Color::Ansi256(39)]);
+    /// ```
+    #[must_use]
+    pub fn prefix_colors(self, colors: impl IntoIterator<Item = Color>) -> Self {
+        self.inner().lock().prefix_colors = colors.into_iter().collect();
+        self
+    }
+
+    /// Keeps a status line at the bottom of the terminal, such as
+    /// `12 done, 6 running, task 12 live`, underneath the output scrolling
+    /// above it.
+    ///
+    /// The line is updated as tasks begin and finish, and erased once the
+    /// Sequencer and all of its tasks have been dropped. Its wording can be
+    /// changed through [`Messages::status`]. This has no effect if the output
+    /// is not a terminal, so that logs do not get littered with status lines.
+    ///
+    /// [`Messages::status`]: crate::Messages::status
+    #[must_use]
+    pub fn status_footer(mut self) -> Self {
+        self.footer = {
+            let inner = &mut *self.inner().lock();
+            let sink = &mut *inner.sink.lock();
+            if sink.is_terminal() {
+                let status = Arc::new(Mutex::new(String::new()));
+                let primary = mem::replace(sink, Box::new(io::sink()));
+                *sink = Box::new(Footer::new(primary, Arc::clone(&status)));
+                inner.footer = Some(status);
+            }
+            inner.footer.is_some()
+        };
+        self
+    }
+
+    /// Ends the output of every task that has a [title][Task::set_title] with
+    /// a line reporting how it went, such as `finished compiling foo v1.2`.
+    ///
+    /// The line is written when the task finishes, in green, or in red if
+    /// the task was [marked as failed][Task::set_failed]. Its wording can be
+    /// changed through [`Messages::title_footer`].
+    ///
+    /// [`Messages::title_footer`]: crate::Messages::title_footer
+    #[must_use]
+    pub fn title_footers(self) -> Self {
+        self.inner().lock().title_footers = true;
+        self
+    }
+
+    /// Ends the output of every task with a dim line reporting how long it
+    /// took, such as `(finished in 3.2s)`.
+    ///
+    /// The time is measured from when the task was begun until it finished,
+    /// which is when its last clone was dropped, the same as
+    /// [`TaskInfo::elapsed`]. Its wording can be changed through
+    /// [`Messages::duration`].
+    ///
+    /// [`Messages::duration`]: crate::Messages::duration
+    #[must_use]
+    pub fn print_durations(self) -> Self {
+        self.inner().lock().durations = true;
+        self
+    }
+
+    /// Puts a timestamp at the start of every line of output.
+    ///
+    /// ```console
+    /// [   0.004s] compiling foo
+    /// [   1.250s] finished foo
+    /// ```
+    ///
+    /// The time is taken when the line is begun by the task, not when it gets
+    /// printed, so buffered output shows when it was actually written just
+    /// like output printed in real time does. Blank lines are left without a
+    /// timestamp.
+    #[must_use]
+    pub fn timestamps(self, format: Timestamps) -> Self {
+        self.inner().lock().clock = Some(Clock::new(format));
+        self
+    }
+
+    /// Sets how many colors the destination can display, in place of what
+    /// gets [detected][ColorDepth::detect] from the environment.
+    ///
+    /// Colors that tasks set using [`Color::Rgb`] or [`Color::Ansi256`] are
+    /// downgraded to the closest color available at this depth.
+    ///
+    /// [`Color::Rgb`]: crate::Color::Rgb
+    /// [`Color::Ansi256`]: crate::Color::Ansi256
+    #[must_use]
+    pub fn color_depth(self, depth: ColorDepth) -> Self {
+        self.inner().lock().color_depth = depth;
+        self
+    }
+
+    /// Sends the output of tasks that match `predicate` to the destination of
+    /// `other` instead of this Sequencer's own.
+    ///
+    /// The routing decision is made once a task has finished, so as to be
+    /// able to take into account everything known about it by then. As a
+    /// consequence, a Sequencer with routes prints nothing in real time; each
+    /// task is printed in its entirety once it is done, still in order. A task
+    /// matching several routes is printed to all of them, and a task matching
+    /// none is printed to this Sequencer's own destination. Output is formatted
+    /// according to the color settings of this Sequencer, not `other`.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::collections::BTreeSet;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let failed = Arc::new(Mutex::new(BTreeSet::new()));
+    ///
+    /// // Failures go to stderr, everything else to stdout.
+    /// let oqueue = Sequencer::stdout().route(Sequencer::stderr(), {
+    ///     let failed = Arc::clone(&failed);
+    ///     move |task| failed.lock().unwrap().contains(&task.index)
+    /// });
+    ///
+    /// let task = oqueue.begin();
+    /// writeln!(task, "error: something went wrong");
+    /// failed.lock().unwrap().insert(task.index);
+    /// ```
+    #[must_use]
+    pub fn route<F>(self, other: Sequencer, predicate: F) -> Self
+    where
+        F: Fn(&TaskInfo) -> bool + Send + 'static,
+    {
+        let sink = Arc::clone(&other.inner().lock().sink);
+        drop(other);
+        self.inner().lock().routes.push(Route {
+            predicate: Box::new(predicate),
+            sink,
+        });
+        self
+    }
+
+    /// Writes a copy of all output to a second destination, such as a log
+    /// file, in addition to where it would usually go.
+    ///
+    /// The copy receives exactly the bytes that are printed, in the same
+    /// order, but with ANSI color codes removed.
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    /// use std::fs::File;
+    ///
+    /// let oqueue = Sequencer::stderr().tee(File::create("build.log")?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn tee<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        {
+            let inner = &mut *self.inner().lock();
+            let sink = &mut *inner.sink.lock();
+            let primary = mem::replace(sink, Box::new(io::sink()));
+            *sink = Box::new(Tee::new(primary, Box::new(writer)));
         }
+        self
+    }
 
-        &mut self.pending[offset]
+    /// Records all output, along with when it was printed, as an [asciicast]
+    /// file, so that the run can be played back later with asciinema
+    /// exactly as it appeared on the terminal.
+    ///
+    /// The recording is the size given by the `COLUMNS` and `LINES`
+    /// environment variables, or else 80×24. Like with [`tee`][Self::tee],
+    /// output that is not colored is recorded without color.
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    /// use std::fs::File;
+    ///
+    /// let cast = File::create("build.cast")?;
+    /// let oqueue = Sequencer::stderr().asciicast(cast);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// [asciicast]: https://docs.asciinema.org/manual/asciicast/v2/
+    #[must_use]
+    pub fn asciicast<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        {
+            let inner = &mut *self.inner().lock();
+            let sink = &mut *inner.sink.lock();
+            let primary = mem::replace(sink, Box::new(io::sink()));
+            *sink = Box::new(Cast::new(primary, Box::new(writer)));
+        }
+        self
     }
-}
 
-impl Output {
-    fn is_done(&self) -> bool {
-        self.done
+    /// Prints around the progress bars of an indicatif [`MultiProgress`], so
+    /// that the bars and the output of tasks do not draw over each other.
+    ///
+    /// The bars are hidden while output is being printed and drawn again
+    /// below it, the same as for [`MultiProgress::println`], so they stay
+    /// at the bottom of the terminal. When combining this with
+    /// [`nonblocking`][Sequencer::nonblocking] or
+    /// [`writer_thread`][Sequencer::writer_thread], call this first, so that
+    /// the bars are hidden by the thread that does the printing.
+    ///
+    /// [`MultiProgress`]: indicatif::MultiProgress
+    /// [`MultiProgress::println`]: indicatif::MultiProgress::println
+    ///
+    /// ```
+    /// use indicatif::{MultiProgress, ProgressBar};
+    /// use oqueue::Sequencer;
+    ///
+    /// let multi = MultiProgress::new();
+    /// let bar = multi.add(ProgressBar::new(10));
+    /// let oqueue = Sequencer::stderr().multi_progress(&multi);
+    ///
+    /// for i in 0..10 {
+    ///     let task = oqueue.begin();
+    ///     writeln!(task, "finished item {}", i);
+    ///     bar.inc(1);
+    /// }
+    /// bar.finish_and_clear();
+    /// ```
+    #[cfg(feature = "indicatif")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "indicatif")))]
+    #[must_use]
+    pub fn multi_progress(self, multi: &indicatif::MultiProgress) -> Self {
+        {
+            let inner = &mut *self.inner().lock();
+            let sink = &mut *inner.sink.lock();
+            let primary = mem::replace(sink, Box::new(io::sink()));
+            *sink = Box::new(Progress::new(primary, multi.clone()));
+        }
+        self
+    }
+
+    /// Prints the output only of tasks that fail, as marked by
+    /// [`Task::set_failed`], and throws away the output of all others.
+    ///
+    /// Nothing is printed in real time in this mode, since whether a task
+    /// fails is only known once it finishes. The output of failed tasks is
+    /// still printed in order, as soon as each one and everything ahead of
+    /// it has finished.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.quiet_unless_failed();
+    ///
+    /// for i in 0..4 {
+    ///     let task = oqueue.begin();
+    ///     writeln!(task, "test {} ...", i);
+    ///     if i == 2 {
+    ///         task.set_failed();
+    ///     }
+    /// }
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(capture.string(), "test 2 ...\n");
+    /// ```
+    #[must_use]
+    pub fn quiet_unless_failed(self) -> Self {
+        self.inner().lock().quiet = true;
+        self
+    }
+
+    /// Stops handing out tasks once `failures` of them have finished after
+    /// being marked failed by [`Task::set_failed`].
+    ///
+    /// From then on [`next_task`][Sequencer::next_task] returns None, so that
+    /// workers pulling tasks from the Sequencer wind down, as for a test runner's
+    /// `--fail-fast`. Tasks already begun run to completion and are printed
+    /// as usual.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().fail_fast(1);
+    /// while let Some(task) = oqueue.next_task() {
+    ///     if task.index == 2 {
+    ///         writeln!(task, "test {} failed", task.index);
+    ///         task.set_failed();
+    ///     }
+    /// }
+    /// assert_eq!(oqueue.summary().tasks, 3);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `failures` is 0.
+    #[must_use]
+    pub fn fail_fast(self, failures: usize) -> Self {
+        assert!(failures > 0, "fail_fast must allow at least one failure");
+        self.inner().lock().fail_fast = Some(failures);
+        self
+    }
+
+    /// Reports each task as a test in the [Test Anything Protocol], for
+    /// harnesses that run tests as oqueue tasks.
+    ///
+    /// Every task becomes one test point in order, `ok` or else `not ok` if
+    /// it was [marked as failed][Task::set_failed], described by its
+    /// [title][Task::set_title] and followed by its output as YAML
+    /// diagnostics, with color removed. Tasks are still printed as soon as
+    /// each one and all those before it are done. The plan comes last, once
+    /// the number of tasks is known at the end of the run.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.tap();
+    ///
+    /// for i in 0..2 {
+    ///     let task = oqueue.begin();
+    ///     task.set_title(format_args!("test {}", i));
+    ///     if i == 1 {
+    ///         writeln!(task, "assertion failed");
+    ///         task.set_failed();
+    ///     }
+    /// }
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(
+    ///     capture.string(),
+    ///     "TAP version 14\n\
+    ///      ok 1 - test 0\n  \
+    ///        ---\n  \
+    ///        output: |\n    \
+    ///          test 0\n  \
+    ///        ...\n\
+    ///      not ok 2 - test 1\n  \
+    ///        ---\n  \
+    ///        output: |\n    \
+    ///          test 1\n    \
+    ///          assertion failed\n  \
+    ///        ...\n\
+    ///      1..2\n",
+    /// );
+    /// ```
+    ///
+    /// [Test Anything Protocol]: https://testanything.org
+    #[must_use]
+    pub fn tap(self) -> Self {
+        self.inner().lock().report = Some(Report::Tap(Tap::new()));
+        self
+    }
+
+    /// Writes a report of the run in JUnit XML format, for CI systems that
+    /// show test results from such reports, without changing what gets
+    /// printed.
+    ///
+    /// Every task becomes one test case, named by its
+    /// [title][Task::set_title] or else by [`Messages::fold_title`], along
+    /// with how long it took, whether it [failed][Task::set_failed] or was
+    /// [cancelled][Task::cancel], and all of its output with color removed.
+    /// The report is written all at once to `writer` at the end of the run.
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    /// use std::fs::File;
+    ///
+    /// let report = File::create("target/junit.xml")?;
+    /// let oqueue = Sequencer::stderr().junit_report(report);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// [`Messages::fold_title`]: crate::Messages::fold_title
+    #[must_use]
+    pub fn junit_report<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.inner().lock().junit = Some(JUnit::new(Box::new(writer)));
+        self
+    }
+
+    /// Writes a web page with the output of the run to `writer`, keeping its
+    /// colors, for publishing the log of a CI job.
+    ///
+    /// The page has a section for each task, in order, headed by the task's
+    /// [title][Task::set_title] or else by [`Messages::fold_title`], which
+    /// can be linked to as `#task-<index>`. Sections of tasks that
+    /// [failed][Task::set_failed] are marked in red. Colors are kept even if
+    /// the output printed by the Sequencer is not colored. The page is
+    /// written all at once at the end of the run.
+    ///
+    /// This should be called before beginning any tasks, as a task's output
+    /// only has colors to keep if it was begun afterward.
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    /// use std::fs::File;
+    ///
+    /// let page = File::create("target/log.html")?;
+    /// let oqueue = Sequencer::stderr().html_report(page);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// [`Messages::fold_title`]: crate::Messages::fold_title
+    #[must_use]
+    pub fn html_report<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        {
+            let inner = &mut *self.inner().lock();
+            inner.html = Some(Html::new(Box::new(writer)));
+            let sink = &mut *inner.sink.lock();
+            if !sink.buffer().supports_color() {
+                let primary = mem::replace(sink, Box::new(io::sink()));
+                *sink = Box::new(Ansi::new(primary));
+                inner.blank = sink.buffer();
+            }
+        }
+        self
+    }
+
+    /// Keeps a log file of every task's output in the directory `dir`, in
+    /// addition to printing it, for looking into what happened in a long run
+    /// after the fact.
+    ///
+    /// The directory is created if it does not exist. Each task's output is
+    /// written, with color removed, to `task-<index>-<title>.log` as soon as
+    /// the task finishes, or `task-<index>.log` for a task without a
+    /// [title][Task::set_title]. At the end of the run, `manifest.json` lists
+    /// all of the tasks: the index, name and log file of each, whether it
+    /// [failed][Task::set_failed] or was [cancelled][Task::cancel], and the
+    /// time it took in seconds. Errors writing the files are reported by
+    /// [`wait`][Sequencer::wait].
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().archive("target/logs");
+    /// ```
+    #[must_use]
+    pub fn archive(self, dir: impl AsRef<Path>) -> Self {
+        {
+            let inner = &mut *self.inner().lock();
+            match Archive::new(dir.as_ref().to_owned()) {
+                Ok(archive) => inner.archive = Some(archive),
+                Err(error) => inner.record(Err(error)),
+            }
+        }
+        self
+    }
+
+    /// Writes a line of JSON to `writer` for everything that happens to each
+    /// task, for tools that follow or analyze the run, in parallel with the
+    /// output printed for people.
+    ///
+    /// Every event has the task's `index` and the `time` in seconds since the
+    /// Unix epoch. A task is `started` when it is begun, has `output` each
+    /// time it is written to, giving the `stream` and number of `bytes`, and
+    /// is `finished` when dropped, giving the total `bytes` written to it,
+    /// its `elapsed` time in seconds, and whether it `failed`.
+    ///
+    /// ```text
+    /// {"event":"started","index":0,"time":1714566896.789012}
+    /// {"event":"output","index":0,"time":1714566896.789105,"stream":"stdout","bytes":24}
+    /// {"event":"finished","index":0,"time":1714566897.012345,"bytes":24,"elapsed":0.223333,"failed":false}
+    /// ```
+    ///
+    /// ```no_run
+    /// use oqueue::Sequencer;
+    /// use std::fs::File;
+    ///
+    /// let events = File::create("target/events.ndjson")?;
+    /// let oqueue = Sequencer::stderr().event_log(events);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn event_log<W>(self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.inner().lock().events = Some(Events::new(Box::new(writer)));
+        self
+    }
+
+    /// Prints one line of JSON for each task once it is done, describing the
+    /// task along with all of its output, instead of printing the output
+    /// itself. This is for programs that run some other program's tasks and
+    /// examine the results, while the tasks are written the same as ever.
+    ///
+    /// Each line is an object with the task's `index`, its
+    /// [`title`][Task::set_title] or null, whether it [`failed`] or was
+    /// [`cancelled`], the time it took in seconds as `elapsed`, and
+    /// everything it wrote to either stream as `output`, with color removed.
+    /// Tasks are printed in the usual order, each as soon as it and all those
+    /// before it are done.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.json();
+    ///
+    /// let task = oqueue.begin();
+    /// writeln!(task, "warning: \"unused\"");
+    /// drop(task);
+    /// drop(oqueue);
+    ///
+    /// let line = capture.string();
+    /// let line = line.trim_end();
+    /// assert!(line.starts_with(r#"{"index":0,"title":null,"failed":false,"cancelled":false,"#));
+    /// assert!(line.ends_with(r#","output":"warning: \"unused\"\n"}"#));
+    /// ```
+    ///
+    /// [`failed`]: Task::set_failed
+    /// [`cancelled`]: Task::cancel
+    #[must_use]
+    pub fn json(self) -> Self {
+        self.inner().lock().report = Some(Report::Json);
+        self
+    }
+
+    /// Prints a line at the end of the run counting how many tasks passed and
+    /// how many failed, such as "42 passed, 3 failed".
+    ///
+    /// Tasks are marked as failed using [`Task::set_failed`]. The wording can
+    /// be customized through [`Messages::summary`].
+    #[must_use]
+    pub fn print_summary(self) -> Self {
+        self.inner().lock().summary = true;
+        self
+    }
+
+    /// Prints a line at the end of the run saying the most output that was
+    /// held in memory at once, waiting for its turn to be printed, such as
+    /// "at most 52480 bytes of output held in memory".
+    ///
+    /// This is for choosing how many tasks to run at once on a machine with
+    /// little memory. The same number is available while the run is going on
+    /// from [`Sequencer::stats`]. The wording can be customized through
+    /// [`Messages::peak_memory`].
+    #[must_use]
+    pub fn print_peak_memory(self) -> Self {
+        self.inner().lock().print_peak = true;
+        self
+    }
+
+    /// Counts the tasks begun so far and the outcomes of the ones that have
+    /// finished, for example to choose an exit code.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    ///
+    /// for i in 0..3 {
+    ///     let task = oqueue.begin();
+    ///     if i == 1 {
+    ///         writeln!(task, "error: task {} failed", i);
+    ///         task.set_failed();
+    ///     }
+    /// }
+    ///
+    /// let summary = oqueue.summary();
+    /// assert_eq!((summary.passed, summary.failed), (2, 1));
+    /// if !summary.success() {
+    ///     # return;
+    ///     std::process::exit(1);
+    /// }
+    /// ```
+    pub fn summary(&self) -> Summary {
+        let inner = self.inner().lock();
+        let tasks = self.started.load(Ordering::Relaxed) - inner.first;
+        Summary::new(tasks, &Tally::default(), &inner.tally)
+    }
+
+    /// Counts of the tasks of the run so far, as of right now.
+    ///
+    /// This is for applications that show the progress of the run in a user
+    /// interface of their own, such as how many tasks are running and how
+    /// many are waiting to be printed.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// let slow = oqueue.begin();
+    /// let fast = oqueue.begin();
+    /// write!(fast, "done");
+    /// drop(fast);
+    ///
+    /// let stats = oqueue.stats();
+    /// assert_eq!((stats.started, stats.finished, stats.buffered), (2, 1, 1));
+    /// assert_eq!((stats.pending, stats.bytes), (2, 4));
+    /// assert_eq!(stats.memory, 4);
+    /// # drop(slow);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let inner = self.inner().lock();
+        let started = self.started.load(Ordering::Relaxed);
+        let mut buffered = 0;
+        for output in &inner.pending {
+            buffered += output.finished_tasks();
+        }
+        Stats::new(
+            started - inner.first,
+            inner.tally.passed() + inner.tally.failed(),
+            buffered,
+            inner.written,
+            started - inner.finished,
+            inner.peak_pending,
+            inner.buffered,
+            inner.peak_buffered,
+        )
+    }
+
+    /// Reports what the Sequencer is doing through the [`metrics`] crate, to
+    /// whichever recorder the application has installed, such as a
+    /// Prometheus exporter.
+    ///
+    /// The metrics are:
+    ///
+    /// - `oqueue_queue_depth`, a gauge of how many tasks have been begun but
+    ///   not printed in their entirety;
+    /// - `oqueue_buffered_bytes`, a gauge of how much output is held in
+    ///   memory waiting for its turn to be printed;
+    /// - `oqueue_tasks_completed`, a counter of tasks that finished running,
+    ///   with a `status` label of `passed` or `failed`;
+    /// - `oqueue_head_of_line_wait_seconds`, a histogram of how long each
+    ///   task waited after finishing for the tasks ahead of it to be printed.
+    ///
+    /// The recorder needs to be installed before this is called. If several
+    /// Sequencers report metrics, the gauges add up what all of them are
+    /// doing.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().export_metrics();
+    /// ```
+    ///
+    /// [`metrics`]: https://docs.rs/metrics
+    #[cfg(feature = "metrics")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+    #[must_use]
+    pub fn export_metrics(self) -> Self {
+        self.inner().lock().metrics = Some(Metrics::new());
+        self
+    }
+
+    /// Covers each task begun from now on by an [OpenTelemetry] span, from
+    /// when the task is begun until it is finished, so that the work of a
+    /// run shows up in distributed traces.
+    ///
+    /// The spans are created by the tracer provider installed globally by
+    /// the application, as children of whatever context is current on the
+    /// thread that begins the task. Each is named `oqueue.task` and has the
+    /// attributes `oqueue.index`; `oqueue.title`, if the task was given a
+    /// [title][Task::set_title]; `oqueue.status`, one of `passed`, `failed`
+    /// or `cancelled`; and `oqueue.bytes`, how much output the task wrote.
+    /// The spans of failed tasks have an error status.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().export_spans();
+    /// ```
+    ///
+    /// [OpenTelemetry]: https://docs.rs/opentelemetry
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "opentelemetry")))]
+    #[must_use]
+    pub fn export_spans(self) -> Self {
+        self.inner().lock().spans = true;
+        self
+    }
+
+    /// Reports what the output is waiting on: which task is holding it up and
+    /// for how long, and how much output of each of the tasks after it is
+    /// being held in memory meanwhile.
+    ///
+    /// This is for finding out why a run appears to be stuck, for example
+    /// from a debugging command of the application.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// let slow = oqueue.begin();
+    /// let fast = oqueue.begin();
+    /// writeln!(fast, "done already");
+    /// drop(fast);
+    ///
+    /// let state = oqueue.dump_state();
+    /// assert_eq!(state.head, Some(0));
+    /// assert_eq!(state.tasks[1].buffered, 13);
+    /// eprint!("{}", state);
+    /// # drop(slow);
+    /// ```
+    pub fn dump_state(&self) -> QueueState {
+        let inner = self.inner().lock();
+        let mut tasks = Vec::new();
+        for output in &inner.pending {
+            output.pending(&mut tasks);
+        }
+        QueueState::new(tasks)
+    }
+
+    /// Copies the output that every task has written but that has not been
+    /// printed yet, paired with the task's index, in the order it is going
+    /// to be printed.
+    ///
+    /// The output is left where it is, to be printed in its turn as usual.
+    /// This is for looking at work in progress, such as from a debugger
+    /// command or a handler that dumps the state of a hung process. Output
+    /// that has been [moved to disk][Sequencer::spill_to_disk] is not
+    /// included.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().whole_tasks(true);
+    /// let task = oqueue.begin();
+    /// writeln!(task, "in progress");
+    ///
+    /// let snapshot = oqueue.snapshot();
+    /// assert_eq!(snapshot, [(0, b"in progress\n".to_vec())]);
+    /// ```
+    pub fn snapshot(&self) -> Vec<(usize, Vec<u8>)> {
+        let inner = self.inner().lock();
+        let mut snapshot = Vec::new();
+        for output in &inner.pending {
+            output.snapshot(&mut snapshot);
+        }
+        snapshot
+    }
+
+    /// Number of finished tasks that were marked as failed by
+    /// [`Task::set_failed`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// oqueue.begin().set_failed();
+    /// oqueue.begin();
+    /// assert_eq!(oqueue.failures(), 1);
+    /// ```
+    pub fn failures(&self) -> usize {
+        self.inner().lock().tally.failed()
+    }
+
+    /// Waits for every task begun so far to be printed, as by
+    /// [`wait`][Sequencer::wait], and returns the status for the process to
+    /// exit with: 0 if none of the tasks failed, otherwise 1. The status is
+    /// also 1 if `wait` reports an error in printing the output, such as a
+    /// full disk or a closed stdout, since then not all of it made it out.
+    ///
+    /// Like `wait`, this must not be called by a thread holding a task that
+    /// is not finished.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// for i in 0..3 {
+    ///     let task = oqueue.begin();
+    ///     writeln!(task, "checking item {}", i);
+    /// }
+    /// # if false {
+    /// std::process::exit(oqueue.into_exit_code());
+    /// # }
+    /// ```
+    pub fn into_exit_code(self) -> i32 {
+        let printed = self.wait();
+        i32::from(self.failures() > 0 || printed.is_err())
+    }
+
+    /// Moves buffered output to temporary files on disk whenever more than
+    /// `bytes` of it is being held in memory.
+    ///
+    /// This bounds the memory used when one slow task holds up the output of
+    /// many noisy ones behind it. Spilled output is read back and printed in
+    /// the usual order when its turn comes, and the files are deleted
+    /// afterward. If a temporary file cannot be created, output simply stays
+    /// in memory.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().spill_to_disk(256 * 1024 * 1024);
+    /// ```
+    #[must_use]
+    pub fn spill_to_disk(self, bytes: usize) -> Self {
+        self.inner().lock().spill_limit = Some(bytes);
+        self
+    }
+
+    /// Calls `f` with the index of each task as it is begun.
+    ///
+    /// Together with [`on_task_complete`][Sequencer::on_task_complete] and
+    /// [`on_task_flushed`][Sequencer::on_task_flushed], this lets something
+    /// like a progress bar or metrics collection observe the run. Callbacks
+    /// are called without any lock held, so they may use the Sequencer.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let bytes = Arc::new(AtomicUsize::new(0));
+    /// let oqueue = Sequencer::stderr()
+    ///     .on_task_start(|index| eprintln!("[started #{}]", index))
+    ///     .on_task_flushed({
+    ///         let bytes = Arc::clone(&bytes);
+    ///         move |info| {
+    ///             bytes.fetch_add(info.bytes, Ordering::Relaxed);
+    ///         }
+    ///     });
+    ///
+    /// let task = oqueue.begin();
+    /// writeln!(task, "hello");
+    /// drop(task);
+    /// assert_eq!(bytes.load(Ordering::Relaxed), 6);
+    /// ```
+    #[must_use]
+    pub fn on_task_start<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_start.push(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` as soon as each task finishes, which is when the last clone
+    /// of its `Task` is dropped. Its output may still be waiting for earlier
+    /// tasks at this point.
+    #[must_use]
+    pub fn on_task_complete<F>(self, f: F) -> Self
+    where
+        F: Fn(&TaskInfo) + Send + Sync + 'static,
+    {
+        self.inner().lock().hooks.complete.push(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` once each finished task's output has all been printed.
+    #[must_use]
+    pub fn on_task_flushed<F>(self, f: F) -> Self
+    where
+        F: Fn(&TaskInfo) + Send + Sync + 'static,
+    {
+        self.inner().lock().hooks.flushed.push(Arc::new(f));
+        self
+    }
+
+    /// Limits how many tasks may be begun but not yet printed.
+    ///
+    /// When one slow task holds up the output of all the tasks after it, their
+    /// output accumulates in memory. With this limit, [`begin`] and
+    /// [`next_task`] block until enough of that output has been printed. Threads must not wait to begin a task while holding a task
+    /// that is yet to be printed, as that task could then never finish.
+    ///
+    /// The limit has no effect in [batch][Sequencer::batch] mode, where
+    /// nothing is printed before the end of the run.
+    ///
+    /// [`begin`]: Sequencer::begin
+    /// [`next_task`]: Sequencer::next_task
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tasks` is 0.
+    #[must_use]
+    pub fn max_pending(mut self, tasks: usize) -> Self {
+        assert!(tasks > 0, "max_pending must allow at least one task");
+        self.max_pending = Some(tasks);
+        self
+    }
+
+    /// Numbers tasks starting from `index` instead of from 0.
+    ///
+    /// This is useful when a run is one chunk of a larger distributed
+    /// workload, such that task indices remain globally meaningful. For example
+    /// a machine responsible for items 1000..2000 would begin its first task
+    /// at index 1000.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().with_start_index(1000);
+    /// let task = oqueue.begin();
+    /// assert_eq!(task.index, 1000);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if any task has already been begun.
+    #[must_use]
+    pub fn with_start_index(mut self, index: usize) -> Self {
+        let started = {
+            let inner = &mut *self.inner().lock();
+            let started = self.started.load(Ordering::Relaxed);
+            assert!(
+                started == inner.finished && inner.pending.is_empty(),
+                "with_start_index must be called before beginning any tasks",
+            );
+            inner.finished = index;
+            inner.first = index;
+            self.started.store(index, Ordering::Relaxed);
+            started
+        };
+        if let Some(end) = &mut self.end {
+            *end = index + (*end - started);
+        }
+        self
+    }
+
+    /// Sets the total number of tasks to be performed, counting from the next
+    /// one to be begun.
+    ///
+    /// Once that many tasks have been begun, [`next_task`][Sequencer::next_task]
+    /// returns None, so workers can stop without using up any index past the
+    /// end. From then on [`begin`][Sequencer::begin] returns tasks whose
+    /// output is thrown away.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let work = vec!["first", "second", "third"];
+    /// let oqueue = Sequencer::stderr().with_len(work.len());
+    ///
+    /// rayon::scope(|scope| {
+    ///     for _ in 0..4 {
+    ///         scope.spawn(|_| {
+    ///             while let Some(task) = oqueue.next_task() {
+    ///                 writeln!(task, "{}", work[task.index]);
+    ///             }
+    ///         });
+    ///     }
+    /// });
+    /// ```
+    #[must_use]
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.end = Some(self.started.load(Ordering::Relaxed) + len);
+        self
+    }
+
+    /// Collapses a line that a task writes many times in a row into just one
+    /// copy, followed by a line saying how many times it was written, such
+    /// as `(repeated 3000 times)`.
+    ///
+    /// This is for child processes that print the same warning over and
+    /// over. Lines count as the same if the task wrote exactly the same
+    /// bytes, each as a whole line in a single write. The wording of the
+    /// count can be changed through [`Messages::repeated`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.dedup_lines();
+    ///
+    /// let task = oqueue.begin();
+    /// for _ in 0..3 {
+    ///     writeln!(task, "warning: deprecated");
+    /// }
+    /// writeln!(task, "done");
+    /// drop(task);
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(
+    ///     capture.string(),
+    ///     "warning: deprecated\n(repeated 3 times)\ndone\n",
+    /// );
+    /// ```
+    ///
+    /// [`Messages::repeated`]: crate::Messages::repeated
+    #[must_use]
+    pub fn dedup_lines(self) -> Self {
+        self.inner().lock().dedup = true;
+        self
+    }
+
+    /// Removes from the output of tasks all escape sequences except the ones
+    /// setting colors and text attributes, and control characters other than
+    /// tab, newline and carriage return.
+    ///
+    /// This is for passing through the output of arbitrary child processes,
+    /// which could otherwise move the cursor, clear the screen, change the
+    /// title of the terminal window, or make it beep. Colors set through
+    /// [`Task::color`] and the like are not affected.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.sanitize();
+    ///
+    /// let task = oqueue.begin();
+    /// writeln!(task, "\x1b[2J\x1b]0;pwned\x07\x1b[1mbold\x1b[0m\x08");
+    /// drop(task);
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(capture.string(), "bold\n");
+    /// ```
+    #[must_use]
+    pub fn sanitize(self) -> Self {
+        self.inner().lock().sanitize = true;
+        self
+    }
+
+    /// Leaves out of buffered output the lines that a task overwrites by going
+    /// back to the start of the line with a carriage return, keeping only
+    /// what would be left on the screen.
+    ///
+    /// Progress bars of child processes then take up one line instead of
+    /// thousands in the output of a task that was not printed in real time.
+    /// The output of the task printing in real time is passed through as is,
+    /// so that progress shows up live.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.collapse_progress();
+    ///
+    /// let first = oqueue.begin();
+    /// let second = oqueue.begin();
+    /// for percent in (0..=100).step_by(25) {
+    ///     write!(second, "\rdownloading {}%", percent);
+    /// }
+    /// writeln!(second);
+    /// drop(second);
+    /// drop(first);
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(capture.string(), "downloading 100%\n");
+    /// ```
+    #[must_use]
+    pub fn collapse_progress(self) -> Self {
+        self.inner().lock().collapse_progress = true;
+        self
+    }
+
+    /// Limits how many lines each task may write. Lines past the limit are
+    /// dropped, and the task's output ends with a line saying how many, such
+    /// as `(12 lines dropped)`.
+    ///
+    /// Lines that the Sequencer writes by itself, such as titles and footers,
+    /// do not count. The wording of the notice can be changed through
+    /// [`Messages::dropped`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.max_lines(2);
+    ///
+    /// let task = oqueue.begin();
+    /// for i in 0..5 {
+    ///     writeln!(task, "line {}", i);
+    /// }
+    /// drop(task);
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(capture.string(), "line 0\nline 1\n(3 lines dropped)\n");
+    /// ```
+    ///
+    /// [`Messages::dropped`]: crate::Messages::dropped
+    #[must_use]
+    pub fn max_lines(self, lines: usize) -> Self {
+        self.inner().lock().max_lines = Some(lines);
+        self
+    }
+
+    /// Limits how fast each task may write, in bytes per second, averaged over
+    /// about a second. A line that would go over the limit is dropped
+    /// entirely, and the task's output ends with a line saying how many were,
+    /// as for [`max_lines`][Sequencer::max_lines].
+    ///
+    /// This keeps a task that floods its output with progress updates from
+    /// drowning out the rest. A task may write up to `bytes` at once after
+    /// being quiet for a second.
+    #[must_use]
+    pub fn max_bytes_per_second(self, bytes: usize) -> Self {
+        self.inner().lock().max_rate = Some(bytes);
+        self
+    }
+
+    /// Prints only the first `head` and the last `tail` lines of a task whose
+    /// output is longer than that, with a line in between saying how many
+    /// were left out, such as `… 12,431 lines omitted …`.
+    ///
+    /// Lines past the first `head` are held back until the task finishes, so
+    /// the end of a task printing in real time shows up only then. The
+    /// wording of the notice can be changed through [`Messages::omitted`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.unicode(true).elide(1, 2);
+    ///
+    /// let task = oqueue.begin();
+    /// for i in 0..6 {
+    ///     writeln!(task, "line {}", i);
+    /// }
+    /// drop(task);
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(
+    ///     capture.string(),
+    ///     "line 0\n… 3 lines omitted …\nline 4\nline 5\n",
+    /// );
+    /// ```
+    ///
+    /// [`Messages::omitted`]: crate::Messages::omitted
+    #[must_use]
+    pub fn elide(self, head: usize, tail: usize) -> Self {
+        self.inner().lock().elide = Some((head, tail));
+        self
+    }
+
+    /// Keeps only the last `bytes` or so of the output of each task that is
+    /// waiting for its turn to be printed, throwing away the oldest as more
+    /// comes in.
+    ///
+    /// This bounds the memory used by tasks that write a lot, in cases where
+    /// only the end of their output matters, like the last lines before an
+    /// error. Output is cut at the start of a line, and what is kept begins
+    /// with a line saying how much was thrown away, such as `(20480 bytes of
+    /// earlier output discarded)`. The wording can be changed through
+    /// [`Messages::trimmed`]. The task printing in real time is not affected.
+    /// No output is spilled to disk by [`spill_to_disk`] for tasks whose tail
+    /// is kept.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.keep_tail(16);
+    ///
+    /// let first = oqueue.begin();
+    /// let second = oqueue.begin();
+    /// for i in 0..5 {
+    ///     writeln!(second, "line {}", i);
+    /// }
+    /// drop(second);
+    /// drop(first);
+    /// drop(oqueue);
+    ///
+    /// assert_eq!(
+    ///     capture.string(),
+    ///     "(21 bytes of earlier output discarded)\nline 3\nline 4\n",
+    /// );
+    /// ```
+    ///
+    /// [`Messages::trimmed`]: crate::Messages::trimmed
+    /// [`spill_to_disk`]: Sequencer::spill_to_disk
+    #[must_use]
+    pub fn keep_tail(self, bytes: usize) -> Self {
+        self.inner().lock().tail = Some(bytes);
+        self
+    }
+
+    /// Replaces the wording of all text that the Sequencer prints by itself,
+    /// for example to translate it.
+    ///
+    /// See [`Messages`] for the text that can be customized.
+    #[must_use]
+    pub fn messages(self, messages: impl Messages + 'static) -> Self {
+        *self.inner().lock().messages.lock() = Box::new(messages);
+        self
+    }
+
+    /// Replaces the styles of the diagnostics that tasks write using
+    /// [`Task::error`], [`Task::warn`], [`Task::info`] and [`Task::note`].
+    #[must_use]
+    pub fn theme(self, theme: Theme) -> Self {
+        self.inner().lock().theme = theme;
+        self
+    }
+
+    /// Overrides whether the status markers of [`Task::status_ok`] and
+    /// [`Task::status_fail`] are symbols like `✓`, or plain ASCII like
+    /// `[ok]`. By default this is guessed from the locale.
+    #[must_use]
+    pub fn unicode(self, enabled: bool) -> Self {
+        self.inner().lock().unicode = enabled;
+        self
+    }
+
+    fn queue(self, capacity: Option<usize>) -> Self {
+        {
+            let inner = &mut *self.inner().lock();
+            let sink = &mut *inner.sink.lock();
+            let stream = mem::replace(sink, Box::new(io::sink()));
+            *sink = Box::new(Queue::new(stream, capacity));
+            inner.blank = sink.buffer();
+        }
+        self
+    }
+
+    /// Blocks until every task begun so far has finished and all of its output
+    /// has been written to the destination, including in
+    /// [batch][Sequencer::batch] mode.
+    ///
+    /// This must not be called by a thread holding a task that is not
+    /// finished, since it would wait for itself forever.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let (oqueue, capture) = Sequencer::capture();
+    /// let oqueue = oqueue.writer_thread();
+    ///
+    /// let task = oqueue.begin();
+    /// writeln!(task, "hello");
+    /// drop(task);
+    ///
+    /// oqueue.wait()?;
+    /// assert_eq!(capture.string(), "hello\n");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error encountered in printing output that was not
+    /// already returned to a writer of a task, since the previous call to
+    /// `wait`.
+    pub fn wait(&self) -> io::Result<()> {
+        let last = self.started.load(Ordering::Relaxed);
+
+        let mut inner = self.inner().lock();
+        loop {
+            let settled = if inner.batch {
+                (inner.finished..last).all(|index| inner.is_done(index))
+            } else {
+                inner.finished >= last && !inner.emitting
+            };
+            if settled {
+                break;
+            }
+            let popped = Arc::clone(&inner.popped);
+            inner = popped.wait(inner).unwrap_or_else(PoisonError::into_inner);
+        }
+
+        let emission = if inner.batch {
+            Some(inner.detach())
+        } else {
+            None
+        };
+        let sink = Arc::clone(&inner.sink);
+        drop(inner);
+
+        let emitted = match emission {
+            Some(emission) => emission.emit(),
+            None => Ok(()),
+        };
+        let synced = sink.lock().sync();
+
+        let mut inner = self.inner().lock();
+        inner.record(emitted);
+        inner.record(synced);
+        match inner.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Waits for all output to be written, then starts the sequence over, so
+    /// that the next task begun gets index 0 again, or the index set by
+    /// [`with_start_index`][Sequencer::with_start_index].
+    ///
+    /// This is for programs that run in phases, such as a build followed by
+    /// the tests, using one Sequencer throughout. It must not be called while
+    /// other threads may still be beginning tasks of the previous phase.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// for _ in 0..3 {
+    ///     writeln!(oqueue.begin(), "building");
+    /// }
+    ///
+    /// oqueue.reset()?;
+    /// let task = oqueue.begin();
+    /// assert_eq!(task.index, 0);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Same as [`wait`][Sequencer::wait].
+    ///
+    /// # Panics
+    ///
+    /// Panics if any task begun so far is still running.
+    pub fn reset(&self) -> io::Result<()> {
+        {
+            let last = self.started.load(Ordering::Relaxed);
+            let inner = self.inner().lock();
+            for index in inner.finished..last {
+                if !inner.is_done(index) {
+                    drop(inner);
+                    panic!("oqueue reset while task {} is still running", index);
+                }
+            }
+            for &index in inner.parents.keys() {
+                if !inner.is_done(index) {
+                    drop(inner);
+                    panic!("oqueue reset while task {} is still running", index);
+                }
+            }
+        }
+
+        let waited = self.wait();
+
+        let inner = &mut *self.inner().lock();
+        inner.finished = inner.first;
+        inner.pending.clear();
+        inner.parents.clear();
+        inner.completed.clear();
+        inner.claimed = None;
+        self.started.store(inner.first, Ordering::Relaxed);
+        self.children.store(0, Ordering::Relaxed);
+        waited
+    }
+
+    /// Begins a scope that tasks begun from now on must finish within.
+    ///
+    /// When the scope ends, either by calling [`Scope::end`] or by dropping it,
+    /// every task begun since the scope was created is required to have been
+    /// dropped already. Finding one still running is considered a leak, and
+    /// panics. All their output is flushed to the destination, including in
+    /// [batch][Sequencer::batch] mode, and `end` returns a summary of the run.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().with_len(30);
+    ///
+    /// let scope = oqueue.scope();
+    /// rayon::scope(|s| {
+    ///     for _ in 0..10 {
+    ///         s.spawn(|_| {
+    ///             while let Some(task) = oqueue.next_task() {
+    ///                 writeln!(task, "hello from task #{}", task.index);
+    ///             }
+    ///         });
+    ///     }
+    /// });
+    /// let summary = scope.end();
+    /// assert_eq!(summary.tasks, 30);
+    ///
+    /// let finished: usize = summary.workers.iter().map(|w| w.tasks).sum();
+    /// assert_eq!(finished, 30);
+    /// ```
+    pub fn scope(&self) -> Scope<'_> {
+        let before = self.inner().lock().tally.clone();
+        Scope::new(self, self.started.load(Ordering::Relaxed), before)
+    }
+
+    /// Begins the next available task.
+    ///
+    /// The caller may figure out what work to perform based on the index of
+    /// this task available in `task.index`, or by acquiring work from a
+    /// synchronized queue that is shared across workers.
+    ///
+    /// This call does not block, unless a limit has been set using
+    /// [`max_pending`][Sequencer::max_pending], in which case it blocks while
+    /// the limit is reached.
+    ///
+    /// Once every task set by [`with_len`][Sequencer::with_len] has been
+    /// begun, this returns a task that has no place in the sequence, with the
+    /// index one past the last one, and whose output is thrown away. Use
+    /// [`next_task`][Sequencer::next_task] to find out instead.
+    pub fn begin(&self) -> Task {
+        Task::new(self.begin_handle())
+    }
+
+    /// Begins the next available task, or returns None if every task set by
+    /// [`with_len`][Sequencer::with_len] has already been begun, if the
+    /// output [has been closed][Sequencer::is_closed], if too many tasks
+    /// have failed under [`fail_fast`][Sequencer::fail_fast], or if the run
+    /// [has been cancelled][Sequencer::cancel_all].
+    ///
+    /// Without `with_len`, this returns a task as long as the output is open.
+    /// Like [`begin`][Sequencer::begin], this blocks while the limit set by
+    /// [`max_pending`][Sequencer::max_pending] is reached.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stdout();
+    /// // Stops early under `| head`.
+    /// while let Some(task) = oqueue.next_task() {
+    ///     # if task.index == 3 { break; }
+    ///     writeln!(task, "line {}", task.index);
+    /// }
+    /// ```
+    pub fn next_task(&self) -> Option<Task> {
+        if self.inner().lock().is_stopped() {
+            return None;
+        }
+        self.next_handle().map(Task::new)
+    }
+
+    /// Whether whoever was reading the output has gone away, such as `head`
+    /// after printing the lines it wanted, so that printing fails with a
+    /// broken pipe.
+    ///
+    /// Once this happens, output written to tasks is thrown away rather than
+    /// held in memory, and [`next_task`][Sequencer::next_task] returns None,
+    /// so that workers can stop early.
+    pub fn is_closed(&self) -> bool {
+        self.inner().lock().closed
+    }
+
+    /// Prints a notice such as `task 0 still running (5m12s elapsed, 37 tasks
+    /// queued behind it)` whenever the task whose output is printed next goes
+    /// `interval` without writing anything, so that a long silent task does
+    /// not look like the program has frozen.
+    ///
+    /// The notice is repeated every `interval` for as long as the task stays
+    /// silent. It is printed to the destination in between the output of
+    /// tasks, never in the middle of a line, and is not part of the output
+    /// of any task. The wording can be changed through
+    /// [`Messages::still_running`]. There is no watchdog in
+    /// [batch][Sequencer::batch] mode, nor once the Sequencer is dropped.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::time::Duration;
+    ///
+    /// let oqueue = Sequencer::stderr().watchdog(Duration::from_secs(60));
+    /// ```
+    ///
+    /// [`Messages::still_running`]: crate::Messages::still_running
+    #[must_use]
+    pub fn watchdog(mut self, interval: Duration) -> Self {
+        self.inner().lock().watchdog = Some(interval);
+        self.start_ticker();
+        self
+    }
+
+    /// Prints a line such as `(no output for 300s)` whenever nothing at all
+    /// has been printed for `interval`, for CI systems that kill a job after
+    /// some time without output.
+    ///
+    /// Like the notices of the [`watchdog`][Sequencer::watchdog], the line is
+    /// printed in between the output of tasks, never in the middle of a line,
+    /// and is not part of the output of any task, so ordering is unaffected.
+    /// Unlike those, it is printed in [batch][Sequencer::batch] mode too. The
+    /// wording can be changed through [`Messages::keepalive`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::time::Duration;
+    ///
+    /// let oqueue = Sequencer::stdout().keepalive(Duration::from_secs(300));
+    /// ```
+    ///
+    /// [`Messages::keepalive`]: crate::Messages::keepalive
+    #[must_use]
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.inner().lock().keepalive = Some(interval);
+        self.start_ticker();
+        self
+    }
+
+    /// Marks failed any task still running after `timeout`, writing a line
+    /// such as `timed out after 5m00s` to its output.
+    ///
+    /// The task itself is not stopped, and whatever it writes afterward is
+    /// printed as usual. It is up to the task to check
+    /// [`Task::is_cancelled`], which returns true once the task has timed
+    /// out, and give up. The wording can be changed through
+    /// [`Messages::timed_out`].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let oqueue = Sequencer::stderr().timeout(Duration::from_millis(100));
+    /// let task = oqueue.begin();
+    /// while !task.is_cancelled() {
+    ///     thread::sleep(Duration::from_millis(10));
+    /// }
+    /// drop(task);
+    /// assert_eq!(oqueue.failures(), 1);
+    /// ```
+    ///
+    /// [`Messages::timed_out`]: crate::Messages::timed_out
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.inner().lock().timeout = Some(timeout);
+        self.start_ticker();
+        self
+    }
+
+    /// Starts the thread that checks on the tasks, if not already running.
+    fn start_ticker(&mut self) {
+        if self.ticker.is_none() {
+            let inner = Arc::downgrade(self.inner());
+            self.ticker = Some(Ticker::spawn(move || {
+                if let Some(inner) = inner.upgrade() {
+                    let mut guard = inner.lock();
+                    guard.tick();
+                    drop(emit_ready(&inner, guard));
+                }
+            }));
+        }
+    }
+
+    /// Calls off the rest of the run, so that workers wind down.
+    ///
+    /// From then on [`next_task`][Sequencer::next_task] returns None, and
+    /// [`is_cancelled`][Sequencer::is_cancelled] returns true. Tasks already
+    /// begun are not interrupted and their output is printed as usual; they
+    /// can check [`Task::is_cancelled`] to stop early. This can be called
+    /// from any thread, such as from one worker that ran into an error that
+    /// makes the remaining work pointless.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// while let Some(task) = oqueue.next_task() {
+    ///     if task.index == 5 {
+    ///         writeln!(task, "out of disk space");
+    ///         oqueue.cancel_all();
+    ///     }
+    /// }
+    /// assert!(oqueue.is_cancelled());
+    /// assert_eq!(oqueue.summary().tasks, 6);
+    /// ```
+    pub fn cancel_all(&self) {
+        self.inner().lock().cancelled = true;
+    }
+
+    /// Whether [`cancel_all`][Sequencer::cancel_all] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner().lock().cancelled
+    }
+
+    /// Begins the task with the given index, instead of the next available
+    /// one.
+    ///
+    /// This is for when something else already decides which work item each
+    /// worker takes, and the output should be printed in the order of the
+    /// work items. Every index from the start up to the highest one begun
+    /// needs to be begun eventually, or the output after it is held up
+    /// forever. Afterward, [`begin`][Sequencer::begin] continues from the
+    /// index after the highest one begun so far.
+    ///
+    /// This does not wait for room under
+    /// [`max_pending`][Sequencer::max_pending].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// let second = oqueue.begin_at(1);
+    /// let first = oqueue.begin_at(0);
+    /// writeln!(second, "printed second");
+    /// writeln!(first, "printed first");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a task with this index has already been begun.
+    pub fn begin_at(&self, index: usize) -> Task {
+        {
+            let inner = &mut *self.inner().lock();
+            let claimed = index < inner.finished || inner.get(index).claimed;
+            assert!(!claimed, "oqueue task {} has already been begun", index);
+            let output = inner.get(index);
+            output.claimed = true;
+            output.begun = Instant::now();
+            self.started.fetch_max(index + 1, Ordering::Relaxed);
+        }
+        Task::new(self.handle(index))
+    }
+
+    /// Marks the given index as done without output, for a work item that
+    /// turns out to need no task at all.
+    ///
+    /// Output of the tasks after it is printed as if the index had been a
+    /// task that printed nothing. The skipped index does not count as a task
+    /// in the [summary][Sequencer::summary].
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr().with_len(3);
+    /// oqueue.skip(1);
+    /// oqueue.begin_at(0);
+    /// oqueue.begin_at(2);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a task with this index has already been begun or skipped.
+    pub fn skip(&self, index: usize) {
+        let mutex = self.inner();
+        let mut inner = mutex.lock();
+        let claimed = index < inner.finished || inner.get(index).claimed;
+        assert!(!claimed, "oqueue task {} has already been begun", index);
+        let output = inner.get(index);
+        output.claimed = true;
+        output.placeholder = true;
+        output.done = true;
+        self.started.fetch_max(index + 1, Ordering::Relaxed);
+        drop(emit_ready(mutex, inner));
+    }
+
+    /// Performs the next available task by calling `f`, returning whatever `f`
+    /// returns.
+    ///
+    /// The task is finished as soon as `f` returns, including by early return
+    /// or by panicking, so its output can never be held up by a task that
+    /// accidentally stays alive. While `f` runs, the task is
+    /// [entered][Task::enter] as the current task of the thread.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    ///
+    /// let len = oqueue.task(|task| {
+    ///     writeln!(task, "measuring task #{}", task.index);
+    ///     task.index.to_string().len()
+    /// });
+    /// assert_eq!(len, 1);
+    /// ```
+    pub fn task<T>(&self, f: impl FnOnce(&Task) -> T) -> T {
+        let task = self.begin();
+        let _entered = task.enter();
+        f(&task)
+    }
+
+    /// Calls `f` on every item, each call as its own task, spread across one
+    /// worker thread per CPU, and returns the results in the same order as
+    /// the items.
+    ///
+    /// Tasks are begun in the order of the items, so the output is printed in
+    /// that order too. Each task is [entered][Task::enter] as the current
+    /// task of its worker thread while `f` runs.
+    ///
+    /// Items are taken only for as long as [`next_task`][Sequencer::next_task]
+    /// keeps handing out tasks. Once the Sequencer runs out of tasks, as set by
+    /// [`with_len`][Sequencer::with_len], or stops, by being closed, cancelled
+    /// or reaching the limit of [`fail_fast`][Sequencer::fail_fast], the
+    /// remaining items are not passed to `f`, and the returned Vec holds the
+    /// results of only the items before them.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// let words = vec!["apple", "banana", "cherry"];
+    ///
+    /// let lens = oqueue.map_ordered(words, |task, word| {
+    ///     writeln!(task, "measuring {}", word);
+    ///     word.len()
+    /// });
+    /// assert_eq!(lens, [5, 6, 6]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, no more items are taken by any worker, and the panic is
+    /// resumed on the calling thread once the calls already in progress have
+    /// returned.
+    #[cfg(not(no_scoped_threads))]
+    #[allow(clippy::incompatible_msrv)]
+    pub fn map_ordered<I, R, F>(&self, items: I, f: F) -> Vec<R>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        R: Send,
+        F: Fn(&Task, I::Item) -> R + Sync,
+    {
+        let items: Vec<I::Item> = items.into_iter().collect();
+        let len = items.len();
+        let workers = thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+        let items = Mutex::new(items.into_iter().enumerate());
+        let panicked = AtomicBool::new(false);
+        let mut results: Vec<Option<R>> = iter::repeat_with(|| None).take(len).collect();
+
+        thread::scope(|scope| {
+            let worker = || {
+                let _stop = StopOnPanic(&panicked);
+                let mut done = Vec::new();
+                loop {
+                    // Taking the item and beginning its task under one lock
+                    // keeps the task indices in the same order as the items.
+                    let (task, (i, item)) = {
+                        let mut items = items.lock();
+                        if panicked.load(Ordering::Relaxed) {
+                            return done;
+                        }
+                        let next = match items.next() {
+                            Some(next) => next,
+                            None => return done,
+                        };
+                        match self.next_task() {
+                            Some(task) => (task, next),
+                            None => return done,
+                        }
+                    };
+                    let _entered = task.enter();
+                    done.push((i, f(&task, item)));
+                }
+            };
+            let handles: Vec<_> = (0..workers.min(len)).map(|_| scope.spawn(worker)).collect();
+            for handle in handles {
+                match handle.join() {
+                    Ok(done) => {
+                        for (i, result) in done {
+                            results[i] = Some(result);
+                        }
+                    }
+                    Err(payload) => std::panic::resume_unwind(payload),
+                }
+            }
+        });
+
+        results.into_iter().map_while(|result| result).collect()
+    }
+
+    pub(crate) fn begin_handle(&self) -> Handle {
+        match self.next_handle() {
+            Some(handle) => handle,
+            None => self.past_end(),
+        }
+    }
+
+    /// Handle of a task begun after every task set by `with_len`, which
+    /// belongs to a queue of its own so as not to hold up the real ones.
+    /// Anything written to it is thrown away.
+    fn past_end(&self) -> Handle {
+        let index = self.started.load(Ordering::Relaxed);
+        let mut inner = Inner::new(Box::new(Writer::new(Box::new(io::sink()))));
+        inner.finished = index;
+        inner.first = index;
+        inner.closed = true;
+        let output = inner.get(index);
+        output.claimed = true;
+        output.discarded = true;
+        Handle::new(index, Arc::new(Mutex::new(inner)), false)
+    }
+
+    fn next_handle(&self) -> Option<Handle> {
+        let index = if let Some(max_pending) = self.max_pending {
+            if self.is_exhausted() {
+                return None;
+            }
+            let mut inner = self.inner().lock();
+            while self.is_full(&inner, max_pending) {
+                let popped = Arc::clone(&inner.popped);
+                inner = popped.wait(inner).unwrap_or_else(PoisonError::into_inner);
+            }
+            self.claim_next(&mut inner)?
+        } else {
+            self.claim_next(&mut self.inner().lock())?
+        };
+        Some(self.handle(index))
+    }
+
+    /// Takes the next index and claims it, passing over any that
+    /// [`begin_at`][Sequencer::begin_at] claimed first in between taking and
+    /// claiming.
+    fn claim_next(&self, inner: &mut Inner) -> Option<usize> {
+        loop {
+            let index = self.next_index()?;
+            let output = inner.get(index);
+            if !output.claimed {
+                output.claimed = true;
+                return Some(index);
+            }
+        }
+    }
+
+    /// Takes the next index, unless the end has been reached.
+    fn next_index(&self) -> Option<usize> {
+        match self.end {
+            None => Some(self.started.fetch_add(1, Ordering::Relaxed)),
+            Some(end) => self
+                .started
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |index| {
+                    if index < end {
+                        Some(index + 1)
+                    } else {
+                        None
+                    }
+                })
+                .ok(),
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.end
+            .map_or(false, |end| self.started.load(Ordering::Relaxed) >= end)
+    }
+
+    /// Whether the given task was begun by this Sequencer.
+    #[cfg(feature = "macros")]
+    pub(crate) fn owns(&self, task: &Task) -> bool {
+        task.belongs_to(self.inner())
+    }
+
+    /// Sets aside the next `len` indices, to be begun in any order using
+    /// [`begin_reserved`][Sequencer::begin_reserved]. Returns the first one.
+    ///
+    /// Fewer than `len` are set aside if that would go past the end set by
+    /// [`with_len`][Sequencer::with_len]; returned second is how many were.
+    /// Every reserved index must be begun eventually, or handed back to
+    /// [`skip_reserved`][Sequencer::skip_reserved], otherwise the output of
+    /// later tasks is held up forever.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn reserve(&self, len: usize) -> (usize, usize) {
+        let available = |index: usize| match self.end {
+            Some(end) => len.min(end.saturating_sub(index)),
+            None => len,
+        };
+        let first = self
+            .started
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |index| {
+                Some(index + available(index))
+            })
+            .unwrap_or_else(|index| index);
+        (first, available(first))
+    }
+
+    /// Marks as done without output the reserved indices from `first` on,
+    /// up to `len` of them, that never got begun.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn skip_reserved(&self, first: usize, len: usize) {
+        let mutex = self.inner();
+        let mut inner = mutex.lock();
+        for index in first.max(inner.finished)..first + len {
+            let output = inner.get(index);
+            if !output.claimed {
+                output.claimed = true;
+                output.placeholder = true;
+                output.done = true;
+            }
+        }
+        drop(emit_ready(mutex, inner));
+    }
+
+    #[cfg(feature = "rayon")]
+    pub(crate) fn begin_reserved(&self, index: usize) -> Task {
+        Task::new(self.handle(index))
+    }
+
+    /// Sets up a newly begun task. Must not be called while holding the lock.
+    fn handle(&self, index: usize) -> Handle {
+        let handle = {
+            let mutex = self.inner();
+            let mut inner = mutex.lock();
+            let handle = Handle::new(index, Arc::clone(mutex), inner.capture_panics);
+            inner.get(index).claimed = true;
+            let pending =
+                self.started.load(Ordering::Relaxed) - inner.finished + inner.parents.len();
+            inner.peak_pending = inner.peak_pending.max(pending);
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &inner.metrics {
+                metrics.begun();
+            }
+            #[cfg(feature = "opentelemetry")]
+            if inner.spans {
+                inner.get(index).span = Some(TaskSpan::start(index));
+            }
+            if let Some(events) = &mut inner.events {
+                events.started(index);
+            }
+            if self.footer {
+                inner.begun += 1;
+                inner.redraw_footer();
+                drop(emit_ready(mutex, inner));
+            }
+            handle
+        };
+        for hook in &self.on_start {
+            hook(index);
+        }
+        handle
+    }
+
+    fn is_full(&self, inner: &Inner, max_pending: usize) -> bool {
+        let pending = self.started.load(Ordering::Relaxed) - inner.finished + inner.parents.len();
+        !inner.batch && pending >= max_pending
+    }
+
+    /// Begins a task whose output is printed right after the output of
+    /// `parent`, ahead of whichever task would otherwise follow `parent`.
+    ///
+    /// This is for work discovered while performing some other task, such as
+    /// follow-up steps generated from a node of a build graph, whose output
+    /// belongs together with that of the task that generated it rather than at
+    /// the end of the run. If one parent begins several children, their output
+    /// is printed in the order they were begun, each one followed by its own
+    /// children.
+    ///
+    /// The new task's index is counted separately, downward from `usize::MAX`,
+    /// so that it never takes up an index within
+    /// [`with_len`][Sequencer::with_len] or one that a worker would use to
+    /// look up its next work item. The new task does count toward
+    /// [`max_pending`][Sequencer::max_pending], but this call never waits for
+    /// room, as its parent may be the very task holding up the queue. In
+    /// [`completion_order`][Sequencer::completion_order] mode the new task is
+    /// begun by [`begin`][Sequencer::begin] like any other.
+    ///
+    /// ```
+    /// use oqueue::{Sequencer, Task};
+    ///
+    /// fn work(oqueue: &Sequencer, task: Task) {
+    ///     writeln!(task, "building #{}", task.index);
+    ///
+    ///     // Printed after "building", even if other tasks have been begun
+    ///     // in the meantime.
+    ///     let test = oqueue.begin_after(&task);
+    ///     drop(task);
+    ///     writeln!(test, "testing #{}", test.index);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` was begun by a different Sequencer.
+    pub fn begin_after(&self, parent: &Task) -> Task {
+        assert!(parent.belongs_to(self.inner()));
+        if self.inner().lock().completion_order {
+            return self.begin();
+        }
+        let index = {
+            let inner = &mut *self.inner().lock();
+            let index = usize::MAX - self.children.fetch_add(1, Ordering::Relaxed);
+            let child = inner.new_output(index);
+            inner.get(parent.index).children.push_back(child);
+            inner.parents.insert(index, parent.index);
+            index
+        };
+
+        Task::new(self.handle(index))
+    }
+
+    /// Begins a task whose output is printed right after the output of
+    /// `parent`, unless the Sequencer has stopped handing out tasks.
+    ///
+    /// This is [`begin_after`][Sequencer::begin_after] with the same
+    /// conditions as [`next_task`][Sequencer::next_task]: it returns None
+    /// once the Sequencer is closed, cancelled, or has seen as many failures
+    /// as allowed by [`fail_fast`][Sequencer::fail_fast], so that work
+    /// discovered by a task stops being started along with everything else.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` was begun by a different Sequencer.
+    pub fn next_task_after(&self, parent: &Task) -> Option<Task> {
+        if self.inner().lock().is_stopped() {
+            return None;
+        }
+        Some(self.begin_after(parent))
+    }
+}
+
+/// Sequencers to print everything from when something happens to the process.
+#[cfg(any(feature = "interrupt", feature = "exit"))]
+type Registry = OnceCell<Mutex<Vec<Weak<Mutex<Inner>>>>>;
+
+/// Sequencers to print everything from when the user presses Ctrl-C.
+#[cfg(feature = "interrupt")]
+static INTERRUPTIBLE: Registry = OnceCell::new();
+
+/// Sequencers to print everything from when the process exits.
+#[cfg(feature = "exit")]
+static EXITING: Registry = OnceCell::new();
+
+#[cfg(any(feature = "interrupt", feature = "exit"))]
+fn register(registry: &Registry, inner: &Arc<Mutex<Inner>>) {
+    let registered = &mut *registry.get_or_init(|| Mutex::new(Vec::new())).lock();
+    registered.retain(|inner| inner.strong_count() > 0);
+    registered.push(Arc::downgrade(inner));
+}
+
+/// Prints everything buffered by the Sequencers that asked for it, because the
+/// process is about to be ended by Ctrl-C.
+#[cfg(feature = "interrupt")]
+pub(crate) fn interrupted() {
+    flush_registered(&INTERRUPTIBLE);
+}
+
+/// Prints everything buffered by the Sequencers that asked for it, because the
+/// process is exiting.
+#[cfg(feature = "exit")]
+pub(crate) fn exiting() {
+    flush_registered(&EXITING);
+}
+
+#[cfg(any(feature = "interrupt", feature = "exit"))]
+fn flush_registered(registry: &Registry) {
+    let registered = registry.get_or_init(|| Mutex::new(Vec::new())).lock();
+    for mutex in registered.iter().filter_map(Weak::upgrade) {
+        let mut inner = mutex.lock();
+        // Already flushed, or there is nobody left to print to.
+        if inner.closed {
+            continue;
+        }
+        // Let whatever is being printed finish first, to keep it in order.
+        let popped = Arc::clone(&inner.popped);
+        while inner.emitting {
+            inner = popped.wait(inner).unwrap_or_else(PoisonError::into_inner);
+        }
+        // Nobody else gets to print from now on.
+        inner.emitting = true;
+        inner.closed = true;
+        let emission = inner.detach_all();
+        let sinks = iter::once(&inner.sink)
+            .chain(&inner.err_sink)
+            .map(Arc::clone)
+            .collect::<Vec<_>>();
+        drop(inner);
+        let _ = emission.emit();
+        for sink in sinks {
+            let _ = sink.lock().sync();
+        }
+    }
+}
+
+/// Prints whatever output is ready to be printed, unless another thread is
+/// already doing so.
+fn emit_ready<'a>(
+    mutex: &'a Mutex<Inner>,
+    mut inner: MutexGuard<'a, Inner>,
+) -> MutexGuard<'a, Inner> {
+    // Whoever is already emitting will pick up this task's output once they
+    // are done with what they have. In batch mode, the output of tasks is
+    // emitted only once the whole run is over, and only what was streamed
+    // gets printed along the way.
+    if !inner.emitting && (!inner.batch || !inner.streamed.is_empty()) {
+        inner.emitting = true;
+        let mut released = false;
+        loop {
+            let emission = if inner.batch {
+                inner.detach_streamed()
+            } else {
+                inner.detach()
+            };
+            if emission.is_empty() {
+                if released {
+                    break;
+                }
+                // Out of output for now, so none of what was printed gets to
+                // wait for more to join it. Meanwhile other tasks may finish,
+                // hence the second look.
+                let sinks = inner.sinks();
+                drop(inner);
+                for sink in sinks {
+                    let _ = sink.lock().release();
+                }
+                inner = mutex.lock();
+                released = true;
+                continue;
+            }
+            released = false;
+            drop(inner);
+            let emitted = emission.emit();
+            inner = mutex.lock();
+            inner.record(emitted);
+            inner.printed = Instant::now();
+        }
+        #[cfg(feature = "metrics")]
+        inner.report_buffered();
+        inner.emitting = false;
+        inner.popped.notify_all();
+    }
+    inner
+}
+
+impl Inner {
+    fn new(sink: Box<dyn Sink>) -> Self {
+        Inner {
+            blank: sink.buffer(),
+            err_blank: sink.buffer(),
+            sink: Arc::new(Mutex::new(sink)),
+            err_sink: None,
+            routes: Vec::new(),
+            emitting: false,
+            streamed: Vec::new(),
+            batch: false,
+            whole_tasks: false,
+            quiet: false,
+            report: None,
+            junit: None,
+            html: None,
+            archive: None,
+            events: None,
+            completion_order: false,
+            claimed: None,
+            interleaved: false,
+            prefix_colors: Arc::from(&output::PREFIX_COLORS[..]),
+            folding: None,
+            completed: VecDeque::new(),
+            messages: Arc::new(Mutex::new(Box::new(English))),
+            theme: Theme::new(),
+            unicode: theme::unicode(),
+            finished: 0,
+            popped: Arc::new(Condvar::new()),
+            pending: VecDeque::new(),
+            parents: HashMap::new(),
+            first: 0,
+            tally: Tally::default(),
+            written: 0,
+            peak_pending: 0,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "opentelemetry")]
+            spans: false,
+            summary: false,
+            spill_limit: None,
+            tail: None,
+            buffered: 0,
+            peak_buffered: 0,
+            print_peak: false,
+            hooks: Hooks::default(),
+            error: None,
+            closed: false,
+            capture_panics: false,
+            fail_fast: None,
+            watchdog: None,
+            watched: None,
+            keepalive: None,
+            printed: Instant::now(),
+            timeout: None,
+            cancelled: false,
+            footer: None,
+            begun: 0,
+            title_footers: false,
+            durations: false,
+            clock: None,
+            color_depth: ColorDepth::detect(),
+            dedup: false,
+            collapse_progress: false,
+            sanitize: false,
+            max_lines: None,
+            max_rate: None,
+            elide: None,
+            live: None,
+        }
+    }
+
+    /// Whether output written by the given task is printed immediately.
+    fn is_realtime(&mut self, index: usize) -> bool {
+        if self.interleaved {
+            return true;
+        }
+
+        if self.emitting
+            || self.batch
+            || self.whole_tasks
+            || self.quiet
+            || self.report.is_some()
+            || !self.routes.is_empty()
+        {
+            return false;
+        }
+
+        if self.completion_order {
+            return *self.claimed.get_or_insert(index) == index;
+        }
+
+        let mut head = match self.pending.front() {
+            Some(front) => front,
+            None => return index == self.finished,
+        };
+
+        while head.done {
+            head = match head.children.front() {
+                Some(child) => child,
+                None => return false,
+            };
+        }
+
+        head.index == index
+    }
+
+    /// Keeps hold of the outcome of printing, to be reported by `wait`.
+    fn record(&mut self, result: io::Result<()>) {
+        if let Err(error) = result {
+            if error.kind() == io::ErrorKind::BrokenPipe {
+                self.closed = true;
+            }
+            self.error.get_or_insert(error);
+        }
+    }
+
+    /// Brings the text of the status footer up to date, if there is one.
+    fn refresh_footer(&mut self) {
+        if let Some(footer) = &self.footer {
+            let done = self.tally.passed() + self.tally.failed();
+            let running = self.begun.saturating_sub(done);
+            *footer.lock() = self.messages.lock().status(done, running, self.live);
+        }
+    }
+
+    /// Checks on the tasks, on behalf of the watchdog thread.
+    fn tick(&mut self) {
+        self.enforce_timeout();
+        self.watch();
+        self.keep_alive();
+    }
+
+    /// Marks failed every task that has been running for longer than the
+    /// timeout, with a line saying so at the end of its output so far.
+    fn enforce_timeout(&mut self) {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let mut overdue = Vec::new();
+        for output in &self.pending {
+            output.overdue(timeout, &mut overdue);
+        }
+        if overdue.is_empty() {
+            return;
+        }
+        let line = self.messages.lock().timed_out(timeout);
+        for index in overdue {
+            let err = self.get(index).err;
+            let _ = task::apply(self, index, err, |output| output.timed_out(&line));
+        }
+    }
+
+    /// Prints a notice if the task printing next has gone without output for
+    /// longer than the watchdog allows, since it began or since the last
+    /// notice.
+    fn watch(&mut self) {
+        let interval = match self.watchdog {
+            Some(interval) => interval,
+            None => return,
+        };
+        let head = self.pending.iter().position(|output| !output.done);
+        let (head, output) = match head {
+            Some(head) if self.pending[head].claimed => (head, &self.pending[head]),
+            _ => {
+                self.watched = None;
+                return;
+            }
+        };
+        let (index, bytes, begun) = (output.index, output.bytes, output.begun);
+        match &mut self.watched {
+            Some(watched) if watched.index == index && watched.bytes == bytes => {
+                if watched.since.elapsed() < interval {
+                    return;
+                }
+                watched.since = Instant::now();
+            }
+            watched => {
+                *watched = Some(Watched {
+                    index,
+                    bytes,
+                    since: Instant::now(),
+                });
+                return;
+            }
+        }
+        if self.batch || !self.can_notice() {
+            return;
+        }
+        let queued = self
+            .pending
+            .iter()
+            .skip(head + 1)
+            .filter(|output| output.claimed)
+            .count();
+        let line = self
+            .messages
+            .lock()
+            .still_running(index, begun.elapsed(), queued);
+        self.notice(&line);
+    }
+
+    /// Prints a line if nothing has been printed for longer than the
+    /// keepalive allows.
+    fn keep_alive(&mut self) {
+        let interval = match self.keepalive {
+            Some(interval) => interval,
+            None => return,
+        };
+        let silent = self.printed.elapsed();
+        if silent < interval || !self.can_notice() {
+            return;
+        }
+        let line = self.messages.lock().keepalive(silent);
+        self.notice(&line);
+    }
+
+    /// Whether a line can be printed right now between the output of tasks.
+    /// A line in the middle of a line already on the screen would split it,
+    /// and one ahead of output that is still being printed would be out of
+    /// order.
+    fn can_notice(&mut self) -> bool {
+        if self.closed || self.emitting {
+            return false;
+        }
+        match self.pending.iter().find(|output| !output.done) {
+            Some(output) if !output.line_start => {
+                let index = output.index;
+                !self.is_realtime(index)
+            }
+            _ => true,
+        }
+    }
+
+    /// Queues a dimmed line of the Sequencer's own, outside the output of any
+    /// task, to be printed by `emit_ready`.
+    fn notice(&mut self, line: &str) {
+        let mut buffer = self.blank.clone();
+        let mut spec = ColorSpec::new();
+        spec.set_dimmed(true);
+        let _ = buffer.set_color(&spec);
+        let _ = write!(buffer, "{}", line);
+        let _ = buffer.reset();
+        let _ = writeln!(buffer);
+        // Counts as printed already, so the next check does not print another.
+        self.stream(false, buffer);
+        self.printed = Instant::now();
+    }
+
+    /// Brings the status footer up to date and has it drawn again.
+    fn redraw_footer(&mut self) {
+        if self.footer.is_some() {
+            self.refresh_footer();
+            let blank = self.blank.clone();
+            self.stream(false, blank);
+        }
+    }
+
+    /// Index of the task in the main sequence that the given task is nested
+    /// under, along with the chain of descendants leading from there to the
+    /// given task, in reverse.
+    fn lineage(&self, index: usize) -> (usize, Vec<usize>) {
+        let mut lineage = Vec::new();
+        let mut root = index;
+        while let Some(&parent) = self.parents.get(&root) {
+            lineage.push(root);
+            root = parent;
+        }
+        (root, lineage)
+    }
+
+    /// Whether no more tasks are to be handed out to workers asking for one.
+    fn is_stopped(&self) -> bool {
+        self.closed
+            || self.cancelled
+            || self
+                .fail_fast
+                .map_or(false, |failures| self.tally.failed() >= failures)
+    }
+
+    /// Whether the given task has been dropped.
+    fn is_done(&self, index: usize) -> bool {
+        let (root, lineage) = self.lineage(index);
+        if root < self.finished {
+            return true;
+        }
+
+        let mut output = match self.pending.get(root - self.finished) {
+            Some(output) => output,
+            // Tasks get a place in the queue at the latest when dropped.
+            None => return false,
+        };
+
+        for index in lineage.into_iter().rev() {
+            output = match output.children.iter().find(|child| child.index == index) {
+                Some(child) => child,
+                None => return true,
+            };
+        }
+
+        output.done
+    }
+
+    /// Whether a copy of every task's output is needed once it finishes.
+    fn keeps_transcripts(&self) -> bool {
+        self.junit.is_some() || self.html.is_some() || self.archive.is_some()
+    }
+
+    fn set_err_sink(&mut self, sink: Box<dyn Sink>) {
+        self.err_blank = sink.buffer();
+        self.err_sink = Some(Arc::new(Mutex::new(sink)));
+    }
+
+    /// Takes the complete lines written by a task to one of its streams so
+    /// far, for printing in interleaved mode.
+    fn take_lines(&mut self, index: usize, err: bool, all: bool) -> Buffer {
+        let default = self.blank_for(err).clone();
+        let colors = Arc::clone(&self.prefix_colors);
+        self.get(index).take_lines(&default, &colors, all)
+    }
+
+    /// Destination of a task's output stream, or of its error stream.
+    fn sink_for(&self, err: bool) -> &SharedSink {
+        match &self.err_sink {
+            Some(err_sink) if err => err_sink,
+            _ => &self.sink,
+        }
+    }
+
+    /// Queues `buffer` to be printed to `sink_for(err)` by `emit_ready` once
+    /// the lock is released, ahead of any output detached from the tasks.
+    fn stream(&mut self, err: bool, buffer: Buffer) {
+        let sink = Arc::clone(self.sink_for(err));
+        self.streamed.push(Detached {
+            sink,
+            spill: None,
+            buffer,
+        });
+    }
+
+    /// Every destination of output.
+    fn sinks(&self) -> Vec<SharedSink> {
+        let mut sinks = vec![Arc::clone(&self.sink)];
+        sinks.extend(self.err_sink.iter().cloned());
+        sinks.extend(self.routes.iter().map(|route| Arc::clone(&route.sink)));
+        sinks
+    }
+
+    /// Empty buffer of the kind printed by `sink_for(err)`.
+    fn blank_for(&self, err: bool) -> &Buffer {
+        if err {
+            &self.err_blank
+        } else {
+            &self.blank
+        }
+    }
+
+    /// Output of a task not begun yet, set up as configured.
+    fn new_output(&self, index: usize) -> Output {
+        let mut output = Output::new(index, self.blank.clone(), Arc::clone(&self.messages));
+        output.clock = self.clock;
+        output.color_depth = self.color_depth;
+        output.dedup = self.dedup;
+        if self.sanitize {
+            output.sanitizer = Some(Sanitizer::new());
+        }
+        output.limiter = Limiter::new(self.max_lines, self.max_rate);
+        output.elision = self.elide.map(|(head, tail)| Elision::new(head, tail));
+        output
+    }
+
+    fn get(&mut self, index: usize) -> &mut Output {
+        let (root, lineage) = self.lineage(index);
+
+        assert!(root >= self.finished);
+        let offset = root - self.finished;
+
+        while offset >= self.pending.len() {
+            let output = self.new_output(self.finished + self.pending.len());
+            self.pending.push_back(output);
+        }
+
+        let mut output = &mut self.pending[offset];
+        for index in lineage.into_iter().rev() {
+            output = output
+                .children
+                .iter_mut()
+                .find(|child| child.index == index)
+                .unwrap();
+        }
+        output
+    }
+
+    /// Takes only what was streamed, leaving the output of tasks in place.
+    fn detach_streamed(&mut self) -> Emission {
+        Emission::new(mem::take(&mut self.streamed), None, &self.hooks)
+    }
+
+    /// Context for taking output from the tasks in `pending`, which is handed
+    /// back alongside it. With `report`, the reports of the run and the hooks
+    /// for flushed tasks hear about what is taken, as they do in the normal
+    /// course of printing.
+    fn detach_cx(&mut self, report: bool) -> (Detach<'_>, &mut VecDeque<Output>) {
+        let flushed = if report && !self.hooks.flushed.is_empty() {
+            Some(Vec::new())
+        } else {
+            None
+        };
+        let cx = Detach {
+            blank: &self.blank,
+            sink: &self.sink,
+            err_blank: &self.err_blank,
+            err_sink: self.err_sink.as_ref().unwrap_or(&self.sink),
+            routes: &self.routes,
+            quiet: self.quiet,
+            whole_tasks: self.whole_tasks,
+            report: if report { self.report.as_mut() } else { None },
+            detached: mem::take(&mut self.streamed),
+            taken: 0,
+            completed: Vec::new(),
+            flushed,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.as_ref(),
+        };
+        (cx, &mut self.pending)
+    }
+
+    /// Pops all finished tasks from the front of the queue, and takes whatever
+    /// the new head task has buffered so far. Returns the buffers in the order
+    /// they need to be printed.
+    fn detach(&mut self) -> Emission {
+        if self.completion_order {
+            return self.detach_completed();
+        }
+
+        let (mut cx, pending) = self.detach_cx(true);
+        let mut popped = 0;
+        while let Some(front) = pending.front_mut() {
+            if !front.detach(&mut cx) {
+                break;
+            }
+            pending.pop_front();
+            popped += 1;
+        }
+        let Detach {
+            detached,
+            taken,
+            flushed,
+            completed,
+            ..
+        } = cx;
+
+        if popped > 0 {
+            self.finished += popped;
+            self.popped.notify_all();
+        }
+
+        for index in completed {
+            self.parents.remove(&index);
+        }
+
+        self.buffered = self.buffered.saturating_sub(taken);
+        Emission::new(detached, flushed, &self.hooks)
+    }
+
+    /// Tells the metrics recorder how much output is held in memory.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn report_buffered(&mut self) {
+        if let Some(metrics) = &mut self.metrics {
+            metrics.buffered(self.buffered);
+        }
+    }
+
+    /// Takes everything buffered so far, by finished and unfinished tasks
+    /// alike, in order.
+    #[cfg(any(feature = "interrupt", feature = "exit"))]
+    fn detach_all(&mut self) -> Emission {
+        let (mut cx, pending) = self.detach_cx(false);
+        for output in pending {
+            output.take_all(&mut cx);
+        }
+        let (detached, taken) = (cx.detached, cx.taken);
+        self.buffered = self.buffered.saturating_sub(taken);
+        Emission::new(detached, None, &self.hooks)
+    }
+
+    /// Takes finished tasks in the order they finished, as long as no task is
+    /// printing in real time.
+    fn detach_completed(&mut self) -> Emission {
+        let completed = if self.claimed.is_none() {
+            mem::take(&mut self.completed)
+        } else {
+            VecDeque::new()
+        };
+        let finished = self.finished;
+        let (mut cx, pending) = self.detach_cx(true);
+        for index in completed {
+            pending[index - finished].detach(&mut cx);
+        }
+        let Detach {
+            detached,
+            taken,
+            flushed,
+            ..
+        } = cx;
+
+        while let Some(front) = self.pending.front() {
+            if !front.done || self.completed.contains(&front.index) {
+                break;
+            }
+            self.finished += 1;
+            self.pending.pop_front();
+            self.popped.notify_all();
+        }
+
+        self.buffered = self.buffered.saturating_sub(taken);
+        Emission::new(detached, flushed, &self.hooks)
+    }
+}
+
+/// Task that the watchdog is keeping an eye on, and how much output it had
+/// as of when it was last heard from.
+struct Watched {
+    index: usize,
+    bytes: usize,
+    since: Instant,
+}
+
+struct Detach<'a> {
+    blank: &'a Buffer,
+    sink: &'a SharedSink,
+    err_blank: &'a Buffer,
+    err_sink: &'a SharedSink,
+    routes: &'a [Route],
+    quiet: bool,
+    whole_tasks: bool,
+    report: Option<&'a mut Report>,
+    detached: Vec<Detached>,
+    /// Amount of in-memory output taken.
+    taken: usize,
+    /// Tasks that have been detached in their entirety, if anyone is
+    /// interested.
+    flushed: Option<Vec<TaskInfo>>,
+    /// Children that have been detached in their entirety.
+    completed: Vec<usize>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<&'a Metrics>,
+}
+
+impl Emission {
+    fn new(detached: Vec<Detached>, flushed: Option<Vec<TaskInfo>>, hooks: &Hooks) -> Self {
+        let flushed = flushed.unwrap_or_default();
+        let hooks = if flushed.is_empty() {
+            Vec::new()
+        } else {
+            hooks.flushed.clone()
+        };
+        Emission {
+            detached,
+            flushed,
+            hooks,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.detached.is_empty() && self.flushed.is_empty()
+    }
+
+    /// Prints everything, returning the first error encountered.
+    fn emit(self) -> io::Result<()> {
+        let mut result = Ok(());
+        for Detached {
+            sink,
+            spill,
+            buffer,
+        } in self.detached
+        {
+            let sink = &mut *sink.lock();
+            if let Some(spill) = spill {
+                result = result.and(spill.replay(|chunk| sink.print(chunk)));
+            }
+            result = result.and(sink.print_owned(buffer));
+        }
+        for info in &self.flushed {
+            for hook in &self.hooks {
+                hook(info);
+            }
+        }
+        result
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Every task is done by now, but in batch mode none of them has been
+        // printed yet.
+        if self.batch {
+            let _ = self.detach().emit();
+        }
+
+        if let Some(junit) = &mut self.junit {
+            let _ = junit.write();
+        }
+
+        if let Some(html) = &mut self.html {
+            let _ = html.write();
+        }
+
+        if let Some(archive) = &mut self.archive {
+            let _ = archive.write_manifest();
+        }
+
+        if let Some(Report::Tap(tap)) = &self.report {
+            let mut buffer = self.blank.clone();
+            let _ = tap.plan(&mut buffer);
+            let _ = self.sink.lock().print_owned(buffer);
+        }
+
+        if self.summary {
+            let line = self
+                .messages
+                .lock()
+                .summary(self.tally.passed(), self.tally.failed());
+            let mut buffer = self.blank.clone();
+            let _ = writeln!(buffer, "{}", line);
+            let _ = self.sink.lock().print_owned(buffer);
+        }
+
+        if self.print_peak {
+            let line = self.messages.lock().peak_memory(self.peak_buffered);
+            let mut buffer = self.blank.clone();
+            let _ = writeln!(buffer, "{}", line);
+            let _ = self.sink.lock().print_owned(buffer);
+        }
+    }
+}
+
+impl Output {
+    fn new(index: usize, buffer: Buffer, messages: SharedMessages) -> Self {
+        Output {
+            index,
+            console: output::is_console(&buffer),
+            buffer,
+            messages,
+            color: Auto,
+            line_start: true,
+            depth: 0,
+            style: ColorSpec::new(),
+            atomic: 0,
+            stripper: Stripper::new(),
+            sanitizer: None,
+            err: false,
+            earlier: Vec::new(),
+            title: None,
+            transcript: Vec::new(),
+            fold: Fold::Unopened,
+            clock: None,
+            color_depth: ColorDepth::TrueColor,
+            dedup: false,
+            last_line: Vec::new(),
+            repeats: 0,
+            collapse: false,
+            progress: None,
+            limiter: None,
+            elision: None,
+            placeholder: false,
+            claimed: false,
+            discarded: false,
+            failed: false,
+            spill: None,
+            trimmed: 0,
+            notice: 0,
+            timed_out: false,
+            bytes: 0,
+            begun: Instant::now(),
+            elapsed: Duration::from_secs(0),
+            done: false,
+            thread: thread::current().id(),
+            children: VecDeque::new(),
+            #[cfg(feature = "opentelemetry")]
+            span: None,
+        }
+    }
+
+    /// Makes an empty buffer of the kind this task writes its output into.
+    fn blank(&self, default: &Buffer) -> Buffer {
+        match self.color {
+            ColorChoice::Auto => default.clone(),
+            ColorChoice::Never => Buffer::no_color(),
+            ColorChoice::Always | ColorChoice::AlwaysAnsi => Buffer::ansi(),
+        }
+    }
+
+    /// Describes this task and its children, in the order they are going to
+    /// be printed.
+    fn pending(&self, tasks: &mut Vec<PendingTask>) {
+        let elapsed = if self.done {
+            self.elapsed
+        } else if self.claimed {
+            self.begun.elapsed()
+        } else {
+            Duration::from_secs(0)
+        };
+        let mut buffered = self.buffer.len();
+        for segment in &self.earlier {
+            buffered += segment.buffer.len();
+        }
+        tasks.push(PendingTask::new(
+            self.index,
+            self.claimed,
+            self.done,
+            elapsed,
+            buffered,
+        ));
+        for child in &self.children {
+            child.pending(tasks);
+        }
+    }
+
+    /// Number of this task and its children that are finished but not
+    /// printed.
+    fn finished_tasks(&self) -> usize {
+        let mut finished = usize::from(self.done);
+        for child in &self.children {
+            finished += child.finished_tasks();
+        }
+        finished
+    }
+
+    /// Copies the unprinted output of this task and its children, leaving out
+    /// any that have none.
+    fn snapshot(&self, snapshot: &mut Vec<(usize, Vec<u8>)>) {
+        let mut bytes = Vec::new();
+        for segment in &self.earlier {
+            bytes.extend_from_slice(segment.buffer.as_slice());
+        }
+        bytes.extend_from_slice(self.buffer.as_slice());
+        if let Some(progress) = &self.progress {
+            bytes.extend_from_slice(progress.as_slice());
+        }
+        if !bytes.is_empty() {
+            snapshot.push((self.index, bytes));
+        }
+        for child in &self.children {
+            child.snapshot(snapshot);
+        }
+    }
+
+    /// Collects the indices of this task and its children that are still
+    /// running after `timeout`, not counting ones already found to be.
+    fn overdue(&self, timeout: Duration, overdue: &mut Vec<usize>) {
+        if self.claimed && !self.done && !self.timed_out && self.begun.elapsed() >= timeout {
+            overdue.push(self.index);
+        }
+        for child in &self.children {
+            child.overdue(timeout, overdue);
+        }
+    }
+
+    /// Takes the buffered output of this task and then of its children, up to
+    /// the first one still running. Returns whether everything got taken.
+    fn detach(&mut self, cx: &mut Detach) -> bool {
+        if cx.routes.is_empty() && !cx.quiet && cx.report.is_none() {
+            // An atomic block still being written is left for the task to
+            // print in one piece.
+            if self.done || (self.atomic == 0 && !cx.whole_tasks) {
+                self.take(cx);
+            }
+            if !self.done {
+                return false;
+            }
+        } else {
+            // Routed, quiet or reported output is held back until the task is
+            // done.
+            if !self.done {
+                return false;
+            }
+            if let Some(report) = &mut cx.report {
+                self.gather(cx.blank);
+                if !self.placeholder {
+                    let mut buffer = self.blank(cx.blank);
+                    let output = if self.discarded || (cx.quiet && !self.failed) {
+                        &[][..]
+                    } else {
+                        self.buffer.as_slice()
+                    };
+                    let title = self.title.as_deref();
+                    let _ = match report {
+                        Report::Tap(tap) => {
+                            let (failed, skipped) = (self.failed, self.discarded);
+                            tap.test_point(&mut buffer, failed, skipped, title, output)
+                        }
+                        Report::Json => Finished {
+                            index: self.index,
+                            title,
+                            failed: self.failed,
+                            cancelled: self.discarded,
+                            elapsed: self.elapsed,
+                            output,
+                        }
+                        .write(&mut buffer),
+                    };
+                    cx.detached.push(Detached {
+                        sink: Arc::clone(cx.sink),
+                        spill: None,
+                        buffer,
+                    });
+                }
+                cx.taken += self.clear();
+            } else if cx.quiet && !self.failed {
+                cx.taken += self.clear();
+            } else if cx.routes.is_empty() {
+                self.take(cx);
+            } else {
+                // Routed output may need to be copied to more than one sink.
+                self.gather(cx.blank);
+                let info = self.info();
+                let mut routed = false;
+                for route in cx.routes {
+                    if (route.predicate)(&info) {
+                        routed = true;
+                        cx.detached.push(Detached {
+                            sink: Arc::clone(&route.sink),
+                            spill: None,
+                            buffer: self.buffer.clone(),
+                        });
+                    }
+                }
+                if routed {
+                    cx.taken += self.buffer.len();
+                    self.buffer.clear();
+                } else {
+                    self.take(cx);
+                }
+            }
+        }
+
+        if let Some(flushed) = &mut cx.flushed {
+            if !self.placeholder {
+                flushed.push(self.info());
+            }
+        }
+
+        while let Some(child) = self.children.front_mut() {
+            if !child.detach(cx) {
+                return false;
+            }
+            cx.completed.push(child.index);
+            self.children.pop_front();
+        }
+
+        #[cfg(feature = "metrics")]
+        if let (Some(metrics), true) = (&cx.metrics, self.claimed) {
+            metrics.printed(self.begun.elapsed().saturating_sub(self.elapsed));
+        }
+        true
+    }
+
+    fn info(&self) -> TaskInfo {
+        TaskInfo::new(
+            self.index,
+            self.thread,
+            self.bytes,
+            self.elapsed,
+            self.failed,
+        )
+    }
+
+    fn take(&mut self, cx: &mut Detach) {
+        let mut spill = self.spill.take();
+        let blank = self.blank(if self.err { cx.err_blank } else { cx.blank });
+        let buffer = mem::replace(&mut self.buffer, blank);
+        let current = Segment {
+            err: self.err,
+            buffer,
+        };
+        for segment in self.earlier.drain(..).chain(iter::once(current)) {
+            if segment.buffer.is_empty() && spill.is_none() {
+                continue;
+            }
+            cx.taken += segment.buffer.len();
+            let sink = if segment.err { cx.err_sink } else { cx.sink };
+            cx.detached.push(Detached {
+                sink: Arc::clone(sink),
+                spill: spill.take(),
+                buffer: segment.buffer,
+            });
+        }
+    }
+
+    /// Takes the buffered output of this task and of all its children, done
+    /// or not.
+    #[cfg(any(feature = "interrupt", feature = "exit"))]
+    fn take_all(&mut self, cx: &mut Detach) {
+        let _ = self.commit_progress();
+        self.take(cx);
+        for child in &mut self.children {
+            child.take_all(cx);
+        }
+    }
+
+    /// Throws away the buffered output, returning how much memory that freed.
+    pub(super) fn clear(&mut self) -> usize {
+        let mut len = self.buffer.len();
+        for segment in self.earlier.drain(..) {
+            len += segment.buffer.len();
+        }
+        self.buffer.clear();
+        self.spill = None;
+        self.progress = None;
+        len
+    }
+
+    /// Directs subsequent writes to the error stream or to the output stream.
+    /// `default` is an empty buffer of the kind printed by the sink of that
+    /// stream.
+    pub(super) fn switch(&mut self, err: bool, default: &Buffer) {
+        if self.err == err {
+            return;
+        }
+        let _ = self.commit_progress();
+        let blank = self.blank(default);
+        self.console = output::is_console(&blank);
+        let buffer = mem::replace(&mut self.buffer, blank);
+        if !buffer.is_empty() {
+            self.earlier.push(Segment {
+                err: self.err,
+                buffer,
+            });
+        }
+        self.err = err;
+    }
+
+    /// Moves the buffered output to disk, returning how much memory that
+    /// freed.
+    pub(super) fn spill(&mut self, default: &Buffer) -> usize {
+        // Only output from before any switch between streams is spilled, so
+        // that it stays ahead of everything else.
+        if self.err || !self.earlier.is_empty() {
+            return 0;
+        }
+        if self.spill.is_none() {
+            self.spill = Spill::create(self.blank(default)).ok();
+        }
+        let spill = match &mut self.spill {
+            Some(spill) => spill,
+            None => return 0,
+        };
+        if spill.write(self.buffer.as_slice()).is_err() {
+            return 0;
+        }
+        let len = self.buffer.len();
+        self.buffer.clear();
+        len
+    }
+
+    /// Throws away the oldest buffered output so that about `limit` bytes
+    /// remain, returning how much memory that freed. What remains begins with
+    /// a line saying how much was thrown away.
+    pub(super) fn keep_tail(&mut self, limit: usize) -> usize {
+        let segments = self.earlier.iter().map(|segment| segment.buffer.len());
+        let total = self.buffer.len() + segments.sum::<usize>();
+        if total <= limit + self.notice {
+            return 0;
+        }
+
+        // The notice from last time is at the front, so it goes first.
+        let mut excess = total - limit;
+        let mut removed = 0;
+        while let Some(first) = self.earlier.first() {
+            if first.buffer.len() > excess {
+                break;
+            }
+            excess -= first.buffer.len();
+            removed += first.buffer.len();
+            self.earlier.remove(0);
+        }
+        let buffer = match self.earlier.first_mut() {
+            Some(first) => &mut first.buffer,
+            None => &mut self.buffer,
+        };
+
+        // Cut at a line boundary if there is one, else at least not inside of
+        // a character or escape sequence.
+        let bytes = buffer.as_slice();
+        let cut = match bytes[excess..].iter().position(|&b| b == b'\n') {
+            Some(newline) => excess + newline + 1,
+            None => bytes.len() - truncate::truncate_start(bytes, bytes.len() - excess).len(),
+        };
+        removed += cut;
+        self.trimmed += removed - self.notice;
+
+        let mut kept = buffer.clone();
+        kept.clear();
+        let line = self.messages.lock().trimmed(self.trimmed);
+        let mut spec = ColorSpec::new();
+        spec.set_dimmed(true);
+        let _ = kept.set_color(&spec);
+        let _ = kept.write_all(line.as_bytes());
+        let _ = kept.reset();
+        let _ = kept.write_all(b"\n");
+        self.notice = kept.len();
+        let _ = kept.write_all(&buffer.as_slice()[cut..]);
+        let len = buffer.len();
+        *buffer = kept;
+        (removed + len).saturating_sub(buffer.len())
+    }
+
+    /// Brings the output previously moved to disk back into memory, together
+    /// with what was written to either stream, as one buffer.
+    fn gather(&mut self, default: &Buffer) {
+        if self.spill.is_none() && self.earlier.is_empty() {
+            return;
+        }
+        let mut buffer = self.blank(default);
+        self.console = output::is_console(&buffer);
+        if let Some(spill) = self.spill.take() {
+            let _ = spill.replay(|chunk| buffer.write_all(chunk.as_slice()));
+        }
+        for segment in self.earlier.drain(..) {
+            let _ = buffer.write_all(segment.buffer.as_slice());
+        }
+        let _ = buffer.write_all(self.buffer.as_slice());
+        self.buffer = buffer;
+        self.err = false;
+    }
+}
+
+/// Tells the other workers of `map_ordered` to stop taking items if the
+/// worker holding it panics.
+#[cfg(not(no_scoped_threads))]
+struct StopOnPanic<'a>(&'a AtomicBool);
+
+#[cfg(not(no_scoped_threads))]
+impl Drop for StopOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.store(true, Ordering::Relaxed);
+        }
     }
 }
@@ -0,0 +1,849 @@
+use crate::ansi::Stripper;
+use crate::json::Str;
+use crate::sync::Mutex;
+use std::env;
+use std::io::{self, Result, Write};
+use std::sync::mpsc::{self, Receiver, RecvError, SyncSender, TryRecvError};
+use std::sync::{Arc, Condvar, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use termcolor::{Buffer, BufferWriter, ColorChoice};
+
+/// Destination that sequenced output is printed to.
+pub(crate) trait Sink: Send {
+    /// Makes an empty buffer in a format that this sink knows how to print.
+    fn buffer(&self) -> Buffer;
+
+    /// Prints the entire contents of the given buffer.
+    fn print(&mut self, buffer: &Buffer) -> Result<()>;
+
+    /// Prints a buffer that the caller has no further use for, which some
+    /// sinks can take without copying.
+    fn print_owned(&mut self, buffer: Buffer) -> Result<()> {
+        self.print(&buffer)
+    }
+
+    /// Begins writing out anything held back, without necessarily waiting
+    /// for it to finish.
+    fn flush(&mut self) -> Result<()>;
+
+    /// Writes out whatever is being held back only to be written in larger
+    /// chunks, so that it does not sit waiting for output that may never
+    /// come. Unlike `flush`, this leaves alone any buffering of the
+    /// underlying writer.
+    fn release(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes, and waits until everything printed so far has been written.
+    fn sync(&mut self) -> Result<()> {
+        self.flush()
+    }
+
+    /// Whether the output is shown on a terminal.
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+/// Amount of output accumulated before writing to a stream that is not a
+/// terminal.
+const STAGING_CAPACITY: usize = 64 * 1024;
+
+/// Longest that accumulated output waits for more to join it before being
+/// written anyway.
+const STAGING_DELAY: Duration = Duration::from_millis(100);
+
+/// Standard output or standard error of the process.
+///
+/// On a terminal, everything printed is flushed right away so that it shows
+/// up with the least latency. Otherwise, such as when redirected to a pipe or
+/// file, output is accumulated and written in large chunks for throughput,
+/// until the Sequencer runs out of output to print or a short delay passes.
+pub(crate) struct Stream {
+    shared: Arc<Shared>,
+    /// The other one of stdout and stderr, if made together by `pair`.
+    other: Option<Arc<Shared>>,
+    terminal: bool,
+    /// Writes out staged output once it has waited long enough.
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Notified when output is first staged, and when the stream is dropped.
+    changed: Condvar,
+}
+
+struct State {
+    writer: BufferWriter,
+    flush: fn() -> Result<()>,
+    staged: Option<Buffer>,
+    /// When the oldest of the staged output was printed.
+    since: Instant,
+    /// First error writing out staged output in the background, to be
+    /// returned by the next print.
+    error: Option<io::Error>,
+    closed: bool,
+}
+
+impl Stream {
+    pub(crate) fn stdout(choice: ColorChoice) -> Self {
+        let terminal = is_terminal(&io::stdout());
+        let writer = BufferWriter::stdout(resolve(choice, terminal));
+        Stream::new(writer, || io::stdout().flush(), terminal)
+    }
+
+    pub(crate) fn stderr(choice: ColorChoice) -> Self {
+        let terminal = is_terminal(&io::stderr());
+        let writer = BufferWriter::stderr(resolve(choice, terminal));
+        Stream::new(writer, || io::stderr().flush(), terminal)
+    }
+
+    fn new(writer: BufferWriter, flush: fn() -> Result<()>, terminal: bool) -> Self {
+        let state = State {
+            writer,
+            flush,
+            staged: staging(terminal),
+            since: Instant::now(),
+            error: None,
+            closed: false,
+        };
+        Stream {
+            shared: Arc::new(Shared {
+                state: Mutex::new(state),
+                changed: Condvar::new(),
+            }),
+            other: None,
+            terminal,
+            thread: None,
+        }
+    }
+
+    /// Stdout, and stderr for the error stream of its tasks.
+    ///
+    /// Each writes out what the other has staged before writing anything
+    /// itself, so that when both are redirected to the same file, output
+    /// still ends up in the order it was printed.
+    pub(crate) fn pair(choice: ColorChoice) -> (Self, Self) {
+        let mut stdout = Stream::stdout(choice);
+        let mut stderr = Stream::stderr(choice);
+        stdout.other = Some(Arc::clone(&stderr.shared));
+        stderr.other = Some(Arc::clone(&stdout.shared));
+        (stdout, stderr)
+    }
+
+    /// Writes everything through to the stream right away, even if it is not
+    /// a terminal.
+    pub(crate) fn unstaged(self) -> Self {
+        self.shared.state.lock().staged = None;
+        self
+    }
+
+    fn spawn(&mut self) {
+        let shared = Arc::clone(&self.shared);
+        self.thread = thread::Builder::new()
+            .name("oqueue-staging".to_owned())
+            .spawn(move || {
+                let mut state = shared.state.lock();
+                while !state.closed {
+                    if state.staged.as_ref().map_or(true, Buffer::is_empty) {
+                        state = shared
+                            .changed
+                            .wait(state)
+                            .unwrap_or_else(PoisonError::into_inner);
+                        continue;
+                    }
+                    match STAGING_DELAY.checked_sub(state.since.elapsed()) {
+                        Some(remaining) if remaining > Duration::from_secs(0) => {
+                            state = shared
+                                .changed
+                                .wait_timeout(state, remaining)
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .0;
+                        }
+                        _ => {
+                            if let Err(error) = state.print_staged() {
+                                state.error.get_or_insert(error);
+                            }
+                        }
+                    }
+                }
+            })
+            .ok();
+    }
+}
+
+impl State {
+    fn print_staged(&mut self) -> Result<()> {
+        match &mut self.staged {
+            Some(staged) if !staged.is_empty() => {
+                let printed = self.writer.print(staged);
+                staged.clear();
+                printed.and_then(|()| (self.flush)())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Decides on `Auto` according to the conventions of the environment:
+///
+/// - `CLICOLOR_FORCE` set to anything but `0` turns color on, even if the
+///   stream is not a terminal;
+/// - otherwise `NO_COLOR` set to anything nonempty, `CLICOLOR=0` or
+///   `TERM=dumb` turns color off, and so does a stream that is not a
+///   terminal.
+fn resolve(choice: ColorChoice, terminal: bool) -> ColorChoice {
+    if choice != ColorChoice::Auto {
+        return choice;
+    }
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+    if var("CLICOLOR_FORCE").map_or(false, |value| value != "0") {
+        ColorChoice::Always
+    } else if var("NO_COLOR").is_some()
+        || var("CLICOLOR").map_or(false, |value| value == "0")
+        || var("TERM").map_or(false, |value| value == "dumb")
+        || !terminal
+    {
+        ColorChoice::Never
+    } else {
+        ColorChoice::Auto
+    }
+}
+
+fn staging(terminal: bool) -> Option<Buffer> {
+    if terminal {
+        None
+    } else {
+        Some(Buffer::no_color())
+    }
+}
+
+#[cfg(not(no_is_terminal))]
+#[allow(clippy::incompatible_msrv)]
+fn is_terminal(stream: &impl std::io::IsTerminal) -> bool {
+    stream.is_terminal()
+}
+
+#[cfg(no_is_terminal)]
+fn is_terminal<T>(_stream: &T) -> bool {
+    true
+}
+
+impl Sink for Stream {
+    fn buffer(&self) -> Buffer {
+        self.shared.state.lock().writer.buffer()
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        if let Some(other) = &self.other {
+            let mut other = other.state.lock();
+            if let Err(error) = other.print_staged() {
+                other.error.get_or_insert(error);
+            }
+        }
+
+        let mut state = self.shared.state.lock();
+        if let Some(error) = state.error.take() {
+            return Err(error);
+        }
+        let staged_len = if let Some(staged) = &state.staged {
+            staged.len()
+        } else {
+            state.writer.print(buffer)?;
+            return (state.flush)();
+        };
+
+        if staged_len + buffer.len() > STAGING_CAPACITY {
+            state.print_staged()?;
+        }
+
+        if buffer.len() >= STAGING_CAPACITY {
+            return state.writer.print(buffer);
+        }
+        let staged = state.staged.as_mut().unwrap();
+        let first = staged.is_empty();
+        staged.write_all(buffer.as_slice())?;
+        if first {
+            state.since = Instant::now();
+            drop(state);
+            if self.thread.is_none() {
+                self.spawn();
+            }
+            self.shared.changed.notify_one();
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<()> {
+        self.shared.state.lock().print_staged()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let mut state = self.shared.state.lock();
+        state.print_staged()?;
+        (state.flush)()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.terminal
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.closed = true;
+        let _ = state.print_staged();
+        drop(state);
+        self.shared.changed.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Arbitrary writer supplied by the caller. Output is uncolored by default,
+/// unless a task opts into ANSI color.
+pub(crate) struct Writer {
+    writer: Box<dyn Write + Send>,
+    color: bool,
+}
+
+impl Writer {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Self {
+        Writer {
+            writer,
+            color: false,
+        }
+    }
+
+    /// Colors all the output using ANSI escape sequences.
+    pub(crate) fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl Sink for Writer {
+    fn buffer(&self) -> Buffer {
+        if self.color {
+            Buffer::ansi()
+        } else {
+            Buffer::no_color()
+        }
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        self.writer.write_all(buffer.as_slice())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Sink that also writes a copy of everything to a second writer, with color
+/// removed.
+pub(crate) struct Tee {
+    primary: Box<dyn Sink>,
+    copy: Box<dyn Write + Send>,
+    stripper: Stripper,
+    stripped: Vec<u8>,
+}
+
+impl Tee {
+    pub(crate) fn new(primary: Box<dyn Sink>, copy: Box<dyn Write + Send>) -> Self {
+        Tee {
+            primary,
+            copy,
+            stripper: Stripper::new(),
+            stripped: Vec::new(),
+        }
+    }
+}
+
+impl Sink for Tee {
+    fn buffer(&self) -> Buffer {
+        self.primary.buffer()
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        let printed = self.primary.print(buffer);
+        self.stripped.clear();
+        self.stripper.strip(buffer.as_slice(), &mut self.stripped);
+        let copied = self.copy.write_all(&self.stripped);
+        printed.and(copied)
+    }
+
+    fn release(&mut self) -> Result<()> {
+        self.primary.release()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let flushed = self.primary.flush();
+        flushed.and(self.copy.flush())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        let synced = self.primary.sync();
+        synced.and(self.copy.flush())
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.primary.is_terminal()
+    }
+}
+
+/// Sink whose buffers are always colored with ANSI escape sequences, so that
+/// a record of the output can keep its color. The color is removed again
+/// before printing to a primary sink that is not colored.
+pub(crate) struct Ansi {
+    primary: Box<dyn Sink>,
+    stripper: Stripper,
+    stripped: Vec<u8>,
+}
+
+impl Ansi {
+    pub(crate) fn new(primary: Box<dyn Sink>) -> Self {
+        Ansi {
+            primary,
+            stripper: Stripper::new(),
+            stripped: Vec::new(),
+        }
+    }
+}
+
+impl Sink for Ansi {
+    fn buffer(&self) -> Buffer {
+        Buffer::ansi()
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        self.stripped.clear();
+        self.stripper.strip(buffer.as_slice(), &mut self.stripped);
+        let mut plain = self.primary.buffer();
+        plain.write_all(&self.stripped)?;
+        self.primary.print_owned(plain)
+    }
+
+    fn release(&mut self) -> Result<()> {
+        self.primary.release()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.primary.flush()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.primary.sync()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.primary.is_terminal()
+    }
+}
+
+/// Sink that also records everything printed, along with when it was
+/// printed, as an asciicast v2 file that asciinema can play back.
+///
+/// <https://docs.asciinema.org/manual/asciicast/v2/>
+pub(crate) struct Cast {
+    primary: Box<dyn Sink>,
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+    /// End of the previous output, if it was cut off in the middle of a UTF-8
+    /// character.
+    partial: Vec<u8>,
+    /// Whether the previous output ended with a carriage return.
+    after_cr: bool,
+}
+
+impl Cast {
+    pub(crate) fn new(primary: Box<dyn Sink>, mut writer: Box<dyn Write + Send>) -> Self {
+        let size = |var, default| {
+            env::var(var)
+                .ok()
+                .and_then(|value| value.parse::<u16>().ok())
+                .unwrap_or(default)
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let _ = writeln!(
+            writer,
+            r#"{{"version":2,"width":{},"height":{},"timestamp":{}}}"#,
+            size("COLUMNS", 80),
+            size("LINES", 24),
+            timestamp,
+        );
+        Cast {
+            primary,
+            writer,
+            start: Instant::now(),
+            partial: Vec::new(),
+            after_cr: false,
+        }
+    }
+
+    fn record(&mut self, bytes: &[u8]) -> Result<()> {
+        self.partial.extend_from_slice(bytes);
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(text) => text.len(),
+            // Incomplete character at the end, to be finished by the next
+            // output.
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(_) => self.partial.len(),
+        };
+        if valid == 0 {
+            return Ok(());
+        }
+        let rest = self.partial.split_off(valid);
+        let text = String::from_utf8_lossy(&self.partial);
+
+        // The player is a terminal, which needs a carriage return to go along
+        // with every line feed.
+        let mut data = String::with_capacity(text.len());
+        for ch in text.chars() {
+            if ch == '\n' && !self.after_cr {
+                data.push('\r');
+            }
+            self.after_cr = ch == '\r';
+            data.push(ch);
+        }
+        self.partial = rest;
+
+        let time = self.start.elapsed().as_secs_f64();
+        writeln!(self.writer, r#"[{:.6},"o",{}]"#, time, Str(&data))
+    }
+}
+
+impl Sink for Cast {
+    fn buffer(&self) -> Buffer {
+        self.primary.buffer()
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        let printed = self.primary.print(buffer);
+        let recorded = self.record(buffer.as_slice());
+        printed.and(recorded)
+    }
+
+    fn release(&mut self) -> Result<()> {
+        self.primary.release()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let flushed = self.primary.flush();
+        flushed.and(self.writer.flush())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        let synced = self.primary.sync();
+        synced.and(self.writer.flush())
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.primary.is_terminal()
+    }
+}
+
+/// Escape sequence returning to the start of the line and erasing it.
+const CLEAR_LINE: &[u8] = b"\r\x1b[2K";
+
+/// Sink that keeps a status line drawn underneath everything printed.
+///
+/// The status line is left without a newline, so that it can be erased and
+/// drawn again below the next output. It is only drawn while the output is at
+/// the start of a line, not in the middle of a line being printed in pieces.
+pub(crate) struct Footer {
+    primary: Box<dyn Sink>,
+    status: Arc<Mutex<String>>,
+    line_start: bool,
+    shown: bool,
+}
+
+impl Footer {
+    pub(crate) fn new(primary: Box<dyn Sink>, status: Arc<Mutex<String>>) -> Self {
+        Footer {
+            primary,
+            status,
+            line_start: true,
+            shown: false,
+        }
+    }
+}
+
+impl Sink for Footer {
+    fn buffer(&self) -> Buffer {
+        self.primary.buffer()
+    }
+
+    /// Prints the buffer in place of the status line, then draws the status
+    /// line again below it. An empty buffer only redraws the status line.
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        let mut out = self.primary.buffer();
+        if self.shown {
+            out.write_all(CLEAR_LINE)?;
+            self.shown = false;
+        }
+        out.write_all(buffer.as_slice())?;
+        if let Some(&last) = buffer.as_slice().last() {
+            self.line_start = last == b'\n';
+        }
+        if self.line_start {
+            let status = self.status.lock();
+            if !status.is_empty() {
+                out.write_all(status.as_bytes())?;
+                self.shown = true;
+            }
+        }
+        self.primary.print_owned(out)
+    }
+
+    fn release(&mut self) -> Result<()> {
+        self.primary.release()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.primary.flush()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.primary.sync()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.primary.is_terminal()
+    }
+}
+
+impl Drop for Footer {
+    fn drop(&mut self) {
+        if self.shown {
+            let mut out = self.primary.buffer();
+            let _ = out.write_all(CLEAR_LINE);
+            let _ = self.primary.print_owned(out);
+            let _ = self.primary.flush();
+        }
+    }
+}
+
+/// Sink that hides the progress bars of an indicatif `MultiProgress` while
+/// printing, and draws them again afterward below what was printed.
+#[cfg(feature = "indicatif")]
+pub(crate) struct Progress {
+    primary: Box<dyn Sink>,
+    multi: indicatif::MultiProgress,
+}
+
+#[cfg(feature = "indicatif")]
+impl Progress {
+    pub(crate) fn new(primary: Box<dyn Sink>, multi: indicatif::MultiProgress) -> Self {
+        Progress { primary, multi }
+    }
+}
+
+#[cfg(feature = "indicatif")]
+impl Sink for Progress {
+    fn buffer(&self) -> Buffer {
+        self.primary.buffer()
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        let Progress { primary, multi } = self;
+        if multi.is_hidden() {
+            return primary.print(buffer);
+        }
+        // Anything still staged once the bars are back would end up mixed
+        // into them.
+        multi.suspend(|| primary.print(buffer).and_then(|()| primary.flush()))
+    }
+
+    fn release(&mut self) -> Result<()> {
+        self.primary.release()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.primary.flush()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.primary.sync()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.primary.is_terminal()
+    }
+}
+
+/// Sink that discards everything. Only used as a placeholder while swapping
+/// out the real sink.
+impl Sink for io::Sink {
+    fn buffer(&self) -> Buffer {
+        Buffer::no_color()
+    }
+
+    fn print(&mut self, _buffer: &Buffer) -> Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink whose printing is performed by a background thread. Printing to the
+/// queue only copies the buffer, unless a bounded queue is full.
+pub(crate) struct Queue {
+    blank: Buffer,
+    terminal: bool,
+    /// First error that the thread ran into, yet to be returned.
+    error: Arc<Mutex<Option<io::Error>>>,
+    sender: Option<Sender>,
+    thread: Option<JoinHandle<()>>,
+}
+
+enum Sender {
+    Bounded(SyncSender<Message>),
+    Unbounded(mpsc::Sender<Message>),
+}
+
+enum Message {
+    Print(Buffer),
+    /// Flush, then report back the outcome if anyone is waiting for it.
+    Flush(Option<SyncSender<Result<()>>>),
+}
+
+impl Queue {
+    /// Queue holding at most `capacity` messages, or any number if None.
+    pub(crate) fn new(mut sink: Box<dyn Sink>, capacity: Option<usize>) -> Self {
+        let blank = sink.buffer();
+        let terminal = sink.is_terminal();
+        let (sender, receiver): (Sender, Receiver<Message>) = if let Some(capacity) = capacity {
+            let (sender, receiver) = mpsc::sync_channel(capacity);
+            (Sender::Bounded(sender), receiver)
+        } else {
+            let (sender, receiver) = mpsc::channel();
+            (Sender::Unbounded(sender), receiver)
+        };
+        let error = Arc::new(Mutex::new(None));
+        let first_error = Arc::clone(&error);
+        let thread = thread::Builder::new()
+            .name("oqueue-writer".to_owned())
+            .spawn(move || {
+                let record = |result: Result<()>| match result {
+                    Ok(()) => true,
+                    Err(error) => {
+                        // Nobody is reading anymore, so there is no point going on.
+                        let closed = error.kind() == io::ErrorKind::BrokenPipe;
+                        first_error.lock().get_or_insert(error);
+                        !closed
+                    }
+                };
+                loop {
+                    let message = match receiver.try_recv() {
+                        Ok(message) => message,
+                        Err(TryRecvError::Empty) => {
+                            // Caught up with everything printed so far.
+                            if !record(sink.release()) {
+                                return;
+                            }
+                            match receiver.recv() {
+                                Ok(message) => message,
+                                Err(RecvError) => break,
+                            }
+                        }
+                        Err(TryRecvError::Disconnected) => break,
+                    };
+                    let going = match message {
+                        Message::Print(buffer) => record(sink.print(&buffer)),
+                        Message::Flush(None) => record(sink.flush()),
+                        Message::Flush(Some(ack)) => {
+                            let flushed = sink.flush();
+                            let closed = matches!(&flushed, Err(error) if error.kind() == io::ErrorKind::BrokenPipe);
+                            let _ = ack.send(flushed);
+                            !closed
+                        }
+                    };
+                    if !going {
+                        return;
+                    }
+                }
+                let _ = sink.flush();
+            })
+            .ok();
+        Queue {
+            blank,
+            terminal,
+            error,
+            sender: Some(sender),
+            thread,
+        }
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        if let Some(error) = self.error.lock().take() {
+            return Err(error);
+        }
+        let sent = match self.sender.as_ref().unwrap() {
+            Sender::Bounded(sender) => sender.send(message).is_ok(),
+            Sender::Unbounded(sender) => sender.send(message).is_ok(),
+        };
+        if sent {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "output thread exited",
+            ))
+        }
+    }
+}
+
+impl Sink for Queue {
+    fn buffer(&self) -> Buffer {
+        self.blank.clone()
+    }
+
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        self.send(Message::Print(buffer.clone()))
+    }
+
+    fn print_owned(&mut self, buffer: Buffer) -> Result<()> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        self.send(Message::Print(buffer))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send(Message::Flush(None))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        let (ack, done) = mpsc::sync_channel(1);
+        self.send(Message::Flush(Some(ack)))?;
+        done.recv().unwrap_or(Ok(()))
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.terminal
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        // Hang up so that the thread finishes printing whatever is still
+        // queued, then exits.
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
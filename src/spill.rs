@@ -0,0 +1,61 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Result, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use termcolor::Buffer;
+
+/// Size of the chunks in which spilled output is read back.
+const CHUNK: usize = 64 * 1024;
+
+/// Temporary file holding the oldest part of a task's output, to relieve
+/// memory while the task waits for its turn to be printed.
+pub(crate) struct Spill {
+    file: File,
+    path: PathBuf,
+    /// Empty buffer of the kind the output was written in.
+    blank: Buffer,
+}
+
+impl Spill {
+    pub(crate) fn create(blank: Buffer) -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("oqueue-{}-{}", process::id(), n));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Spill { file, path, blank })
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.file.write_all(bytes)
+    }
+
+    /// Passes everything that was spilled to `f`, one chunk at a time.
+    pub(crate) fn replay(mut self, mut f: impl FnMut(&Buffer) -> Result<()>) -> Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut chunk = vec![0; CHUNK];
+        let mut buffer = self.blank.clone();
+        loop {
+            let n = match self.file.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            buffer.clear();
+            buffer.write_all(&chunk[..n])?;
+            f(&buffer)?;
+        }
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::Task;
+use termcolor::{Color, ColorSpec};
+
+/// Conversion into termcolor's representation of a text style, for use with
+/// [`Task::set_style`][crate::Task::set_style].
+///
+/// Besides termcolor's own `ColorSpec` and `Color`, this is implemented for
+/// the `Style` types of the owo-colors and yansi crates when the Cargo feature
+/// of oqueue by the same name is enabled.
+pub trait ToColorSpec {
+    fn to_color_spec(&self) -> ColorSpec;
+}
+
+impl ToColorSpec for ColorSpec {
+    fn to_color_spec(&self) -> ColorSpec {
+        self.clone()
+    }
+}
+
+/// A foreground color.
+impl ToColorSpec for Color {
+    fn to_color_spec(&self) -> ColorSpec {
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(*self));
+        spec
+    }
+}
+
+/// Text style being put together for a task, made by [`Task::style`].
+///
+/// Each method adds one attribute to the style. Nothing happens to the task
+/// until [`apply`][StyleBuilder::apply] is called.
+#[must_use = "call .apply() to set the style"]
+pub struct StyleBuilder<'a> {
+    task: &'a Task,
+    spec: ColorSpec,
+}
+
+impl<'a> StyleBuilder<'a> {
+    pub(crate) fn new(task: &'a Task) -> Self {
+        StyleBuilder {
+            task,
+            spec: ColorSpec::new(),
+        }
+    }
+
+    /// Foreground color.
+    pub fn fg(mut self, color: Color) -> Self {
+        self.spec.set_fg(Some(color));
+        self
+    }
+
+    /// Background color.
+    pub fn bg(mut self, color: Color) -> Self {
+        self.spec.set_bg(Some(color));
+        self
+    }
+
+    /// Bold text.
+    pub fn bold(mut self) -> Self {
+        self.spec.set_bold(true);
+        self
+    }
+
+    /// Dim text, in terminals that support it.
+    pub fn dimmed(mut self) -> Self {
+        self.spec.set_dimmed(true);
+        self
+    }
+
+    /// Italic text, in terminals that support it.
+    pub fn italic(mut self) -> Self {
+        self.spec.set_italic(true);
+        self
+    }
+
+    /// Underlined text.
+    pub fn underline(mut self) -> Self {
+        self.spec.set_underline(true);
+        self
+    }
+
+    /// Text with a line through it, in terminals that support it.
+    pub fn strikethrough(mut self) -> Self {
+        self.spec.set_strikethrough(true);
+        self
+    }
+
+    /// High intensity variant of the foreground and background colors, for
+    /// the 8 basic colors.
+    pub fn intense(mut self) -> Self {
+        self.spec.set_intense(true);
+        self
+    }
+
+    /// Sets the task's output to appear in this style, until the next change
+    /// of style.
+    pub fn apply(self) {
+        self.task.set_style(&self.spec);
+    }
+}
+
+/// Style of a task's output that lasts until this guard is dropped, made by
+/// [`Task::styled`].
+///
+/// Dropping the guard puts back whatever style was in effect before it was
+/// made, so guards can be nested.
+#[must_use = "the style is undone immediately if the guard is not held"]
+pub struct StyleGuard<'a> {
+    task: &'a Task,
+    previous: ColorSpec,
+}
+
+impl<'a> StyleGuard<'a> {
+    pub(crate) fn new(task: &'a Task, previous: ColorSpec) -> Self {
+        StyleGuard { task, previous }
+    }
+}
+
+impl Drop for StyleGuard<'_> {
+    fn drop(&mut self) {
+        if self.previous.is_none() {
+            self.task.reset_color();
+        } else {
+            self.task.set_style(&self.previous);
+        }
+    }
+}
+
+#[cfg(feature = "owo-colors")]
+#[cfg_attr(docsrs, doc(cfg(feature = "owo-colors")))]
+impl ToColorSpec for owo_colors::Style {
+    fn to_color_spec(&self) -> ColorSpec {
+        crate::ansi::parse_sgr(&self.prefix_formatter().to_string())
+    }
+}
+
+#[cfg(feature = "yansi")]
+#[cfg_attr(docsrs, doc(cfg(feature = "yansi")))]
+impl ToColorSpec for yansi::Style {
+    fn to_color_spec(&self) -> ColorSpec {
+        crate::ansi::parse_sgr(&self.prefix())
+    }
+}
@@ -1,4 +1,7 @@
-use std::sync::{Mutex as StdMutex, MutexGuard, PoisonError};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::{Mutex as StdMutex, MutexGuard, Once, PoisonError};
 
 /// Non-poisoning mutex.
 pub(crate) struct Mutex<T: ?Sized> {
@@ -14,7 +17,47 @@ impl<T> Mutex<T> {
 }
 
 impl<T: ?Sized> Mutex<T> {
-    pub(crate) fn lock(&self) -> MutexGuard<T> {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
         self.std.lock().unwrap_or_else(PoisonError::into_inner)
     }
 }
+
+/// Cell written at most once, constructible in const context.
+pub(crate) struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    pub(crate) const fn new() -> Self {
+        OnceCell {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub(crate) fn with_value(value: T) -> Self {
+        let cell = OnceCell::new();
+        cell.get_or_init(|| value);
+        cell
+    }
+
+    pub(crate) fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.once.call_once(|| {
+            let value = f();
+            unsafe { (*self.value.get()).as_mut_ptr().write(value) }
+        });
+        unsafe { &*(*self.value.get()).as_ptr() }
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { ptr::drop_in_place(self.value.get_mut().as_mut_ptr()) }
+        }
+    }
+}
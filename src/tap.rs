@@ -0,0 +1,74 @@
+use crate::ansi::Stripper;
+use std::io::{Result, Write};
+
+/// Progress of a run being reported in the Test Anything Protocol.
+///
+/// <https://testanything.org/tap-version-14-specification.html>
+pub(crate) struct Tap {
+    /// Number of test points written so far.
+    tests: usize,
+}
+
+impl Tap {
+    pub(crate) fn new() -> Self {
+        Tap { tests: 0 }
+    }
+
+    /// Writes the test point of a finished task, followed by the task's output
+    /// as YAML diagnostics. A skipped test point is one that was cancelled.
+    pub(crate) fn test_point(
+        &mut self,
+        w: &mut impl Write,
+        failed: bool,
+        skipped: bool,
+        title: Option<&str>,
+        output: &[u8],
+    ) -> Result<()> {
+        if self.tests == 0 {
+            writeln!(w, "TAP version 14")?;
+        }
+        self.tests += 1;
+
+        let status = if failed { "not ok" } else { "ok" };
+        write!(w, "{} {}", status, self.tests)?;
+        if let Some(title) = title {
+            // Everything after a `#` would be taken as a directive.
+            write!(w, " - {}", title.replace('#', "\\#"))?;
+        }
+        if skipped {
+            write!(w, " # SKIP")?;
+        }
+        writeln!(w)?;
+
+        let mut plain = Vec::new();
+        Stripper::new().strip(output, &mut plain);
+        if plain.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&plain);
+        writeln!(w, "  ---")?;
+        // A block scalar figures out its indentation from its first line,
+        // unless told.
+        if text.starts_with(' ') {
+            writeln!(w, "  output: |2")?;
+        } else {
+            writeln!(w, "  output: |")?;
+        }
+        for line in text.lines() {
+            if line.is_empty() {
+                writeln!(w)?;
+            } else {
+                writeln!(w, "    {}", line)?;
+            }
+        }
+        writeln!(w, "  ...")
+    }
+
+    /// Writes the plan, which comes after all the test points.
+    pub(crate) fn plan(&self, w: &mut impl Write) -> Result<()> {
+        if self.tests == 0 {
+            writeln!(w, "TAP version 14")?;
+        }
+        writeln!(w, "1..{}", self.tests)
+    }
+}
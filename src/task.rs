@@ -1,10 +1,17 @@
-use super::{Inner, Output};
+use super::{emit_ready, Detached, Fold, Inner, Output};
+use crate::junit::Case;
+use crate::panic::Panic;
+use crate::style::{StyleBuilder, StyleGuard, ToColorSpec};
 use crate::sync::Mutex;
-use std::fmt::{self, Debug};
-use std::io::{Result, Write};
-use std::rc::Rc;
+use crate::theme::Severity;
+use std::fmt::{self, Debug, Display};
+use std::io::{BufRead, BufReader, Read, Result, Write};
+use std::mem;
+use std::process::{Command, ExitStatus, Stdio};
 use std::sync::Arc;
-use termcolor::{Color, ColorSpec, WriteColor};
+use std::thread;
+use std::time::Instant;
+use termcolor::{Buffer, Color, ColorChoice, ColorSpec, HyperlinkSpec, WriteColor};
 
 /// Unit of work arranged by a Sequencer.
 ///
@@ -15,6 +22,23 @@ use termcolor::{Color, ColorSpec, WriteColor};
 /// Refer to the crate-level documentation and the documentation of the
 /// Sequencer type for the recommended patterns of launching tasks.
 ///
+/// A task can be sent to a different thread than the one that began it, and
+/// clones of it can be shared across threads. The task is finished when the
+/// last clone is dropped.
+///
+/// ```
+/// use oqueue::Sequencer;
+/// use std::thread;
+///
+/// let oqueue = Sequencer::stderr();
+/// let task = oqueue.begin();
+/// thread::spawn(move || {
+///     writeln!(task, "finished on another thread");
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+///
 /// ```
 /// use oqueue::{Color::Blue, Task};
 ///
@@ -26,7 +50,7 @@ use termcolor::{Color, ColorSpec, WriteColor};
 #[readonly::make]
 #[derive(Clone)]
 pub struct Task {
-    handle: Rc<Handle>,
+    handle: Arc<Handle>,
 
     /// Index of the current task. This is a sequential counter that begins at 0
     /// and increments by 1 for each successively started task. It may be
@@ -38,9 +62,13 @@ pub struct Task {
     pub index: usize,
 }
 
-struct Handle {
+/// Shared state of a task, which is finished when the last handle is dropped.
+pub(crate) struct Handle {
     inner: Arc<Mutex<Inner>>,
     index: usize,
+    begun: Instant,
+    /// Whether a panic while this task is current gets written to it.
+    capture_panics: bool,
 }
 
 impl Debug for Task {
@@ -53,13 +81,17 @@ impl Debug for Task {
 }
 
 impl Task {
-    pub(super) fn new(index: usize, inner: Arc<Mutex<Inner>>) -> Self {
+    pub(super) fn new(handle: Handle) -> Self {
         Task {
-            handle: Rc::new(Handle { inner, index }),
-            index,
+            index: handle.index(),
+            handle: Arc::new(handle),
         }
     }
 
+    pub(super) fn belongs_to(&self, inner: &Arc<Mutex<Inner>>) -> bool {
+        Arc::ptr_eq(&self.handle.inner, inner)
+    }
+
     /// Set output to appear in bold uncolored.
     pub fn bold(&self) {
         let mut spec = ColorSpec::new();
@@ -82,25 +114,961 @@ impl Task {
         let _ = self.apply(|w| w.set_color(&spec));
     }
 
+    /// Set output to appear in the given style, which may come from termcolor
+    /// or from another crate's representation of styles.
+    ///
+    /// ```
+    /// use oqueue::{Color, ColorSpec, Task};
+    ///
+    /// fn work(task: Task) {
+    ///     let mut spec = ColorSpec::new();
+    ///     spec.set_fg(Some(Color::Green)).set_underline(true);
+    ///     task.set_style(&spec);
+    ///     writeln!(task, "success");
+    ///     task.reset_color();
+    /// }
+    /// ```
+    pub fn set_style(&self, style: &impl ToColorSpec) {
+        let spec = style.to_color_spec();
+        let _ = self.apply(|w| w.set_color(&spec));
+    }
+
+    /// Set output to appear in a style built up one attribute at a time.
+    ///
+    /// ```
+    /// use oqueue::{Color, Task};
+    ///
+    /// fn work(task: Task) {
+    ///     task.style().underline().bg(Color::Blue).apply();
+    ///     writeln!(task, "heading");
+    ///     task.reset_color();
+    /// }
+    /// ```
+    pub fn style(&self) -> StyleBuilder<'_> {
+        StyleBuilder::new(self)
+    }
+
+    /// Set output to appear in the given style until the returned guard is
+    /// dropped, at which point the previous style comes back.
+    ///
+    /// ```
+    /// use oqueue::{Color, Task};
+    ///
+    /// fn work(task: Task) -> Result<(), String> {
+    ///     let _red = task.styled(Color::Red);
+    ///     writeln!(task, "error:");
+    ///     check()?; // the style is undone here too
+    ///     writeln!(task, "not really");
+    ///     Ok(())
+    /// }
+    /// # fn check() -> Result<(), String> { Ok(()) }
+    /// ```
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn styled(&self, style: impl ToColorSpec) -> StyleGuard<'_> {
+        let spec = style.to_color_spec();
+        let previous = self.apply(|w| {
+            let previous = w.style.clone();
+            w.set_color(&spec)?;
+            Ok(previous)
+        });
+        StyleGuard::new(self, previous.unwrap_or_default())
+    }
+
     /// Set output to non-bold uncolored.
     pub fn reset_color(&self) {
         let _ = self.apply(|w| w.reset());
     }
 
+    /// Override whether this task's output is colored, independent of the
+    /// Sequencer's setting.
+    ///
+    /// With `ColorChoice::Never` the methods for setting color do nothing for
+    /// this task, for example when it writes machine-readable text. With
+    /// `Always` or `AlwaysAnsi` they produce ANSI escape sequences even where
+    /// the rest of the output would be uncolored. `Auto` goes back to the
+    /// Sequencer's default. This applies equally whether the task is the one
+    /// printing in real time or is being buffered.
+    ///
+    /// Pre-rendered color contained in the bytes written to an uncolored task,
+    /// such as text styled using owo-colors or the colored output of a child
+    /// process, has its SGR escape sequences removed. To pass such output
+    /// through unchanged, use `Always`.
+    pub fn color_choice(&self, choice: ColorChoice) {
+        let inner = &mut *self.handle.inner.lock();
+        let default = inner.blank.clone();
+        let output = inner.get(self.handle.index);
+        output.color = choice;
+        let mut buffer = output.blank(&default);
+        output.console = super::output::is_console(&buffer);
+        let _ = buffer.write_all(output.buffer.as_slice());
+        output.buffer = buffer;
+    }
+
+    /// Write `text` as a link to `url`, which terminals that support OSC 8
+    /// hyperlinks make clickable.
+    ///
+    /// If the output is not going to be colored, only the text is written,
+    /// since there is no telling whether the destination would understand the
+    /// escape sequence. For more control, for example to link text written in
+    /// several pieces, use [`WriteColor::set_hyperlink`] directly.
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn report(task: Task, path: &str, line: usize) {
+    ///     task.hyperlink(&format!("file://{}", path), format_args!("{}:{}", path, line));
+    ///     writeln!(task, ": unused variable");
+    /// }
+    /// ```
+    pub fn hyperlink(&self, url: &str, text: impl Display) {
+        let _ = self.apply(|output| {
+            if !output.supports_hyperlinks() {
+                return write!(output, "{}", text);
+            }
+            output.set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))?;
+            write!(output, "{}", text)?;
+            output.set_hyperlink(&HyperlinkSpec::close())
+        });
+    }
+
+    /// Write an error message, on a line of its own after a label like
+    /// `error:` in the style of the Sequencer's [theme].
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn work(task: Task, path: &str) {
+    ///     task.error(format_args!("failed to read {}", path));
+    ///     task.note("the file may have been moved");
+    /// }
+    /// ```
+    ///
+    /// [theme]: crate::Sequencer::theme
+    pub fn error(&self, message: impl Display) {
+        self.diagnostic(Severity::Error, &message);
+    }
+
+    /// Write a warning, labeled `warning:`. Refer to [`error`][Task::error].
+    pub fn warn(&self, message: impl Display) {
+        self.diagnostic(Severity::Warning, &message);
+    }
+
+    /// Write an informational message, labeled `info:`. Refer to
+    /// [`error`][Task::error].
+    pub fn info(&self, message: impl Display) {
+        self.diagnostic(Severity::Info, &message);
+    }
+
+    /// Write a note, labeled `note:`. Refer to [`error`][Task::error].
+    pub fn note(&self, message: impl Display) {
+        self.diagnostic(Severity::Note, &message);
+    }
+
+    /// Write `message` after a marker of success, such as `✓`, on a line of
+    /// its own.
+    ///
+    /// The marker is in the style of the Sequencer's [theme]. It falls back
+    /// to ASCII, `[ok]`, where the terminal seems not to be able to render
+    /// anything else; refer to [`Sequencer::unicode`].
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn work(task: Task, name: &str, passed: bool) {
+    ///     if passed {
+    ///         task.status_ok(name);
+    ///     } else {
+    ///         task.status_fail(name);
+    ///         task.set_failed();
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [theme]: crate::Sequencer::theme
+    /// [`Sequencer::unicode`]: crate::Sequencer::unicode
+    pub fn status_ok(&self, message: impl Display) {
+        self.status(true, &message);
+    }
+
+    /// Write `message` after a marker of failure, such as `✗` or `[FAIL]`.
+    /// Refer to [`status_ok`][Task::status_ok].
+    pub fn status_fail(&self, message: impl Display) {
+        self.status(false, &message);
+    }
+
+    fn status(&self, ok: bool, message: &dyn Display) {
+        let (marker, spec) = {
+            let inner = &*self.handle.inner.lock();
+            let spec = inner.theme.status(ok).clone();
+            let marker = inner.messages.lock().marker(ok, inner.unicode);
+            (marker, spec)
+        };
+        self.labeled(&spec, &marker, message);
+    }
+
+    fn diagnostic(&self, severity: Severity, message: &dyn Display) {
+        let (label, spec) = {
+            let inner = &*self.handle.inner.lock();
+            let spec = inner.theme.style(severity).clone();
+            let label = inner.messages.lock().label(severity) + ":";
+            (label, spec)
+        };
+        self.labeled(&spec, &label, message);
+    }
+
+    /// Writes a line made of `label` in the given style, then `message`.
+    fn labeled(&self, spec: &ColorSpec, label: &str, message: &dyn Display) {
+        let _ = self.apply(|output| {
+            if !output.line_start {
+                output.write_all(b"\n")?;
+            }
+            let previous = output.style.clone();
+            output.set_color(spec)?;
+            output.write_all(label.as_bytes())?;
+            if previous.is_none() {
+                output.reset()?;
+            } else {
+                output.set_color(&previous)?;
+            }
+            writeln!(output, " {}", message)
+        });
+    }
+
+    /// Begin a section of this task's output, headed by `title`.
+    ///
+    /// Output written to the task while the returned guard is alive is nested
+    /// under the header. On a terminal this is rendered as the title in bold
+    /// on its own line, followed by the section's contents indented by one
+    /// level. Sections may themselves contain sections. The section ends when
+    /// the guard is dropped.
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn work(task: Task) {
+    ///     writeln!(task, "building");
+    ///     {
+    ///         let _section = task.section("compiling");
+    ///         writeln!(task, "foo v1.0.0");
+    ///         writeln!(task, "bar v0.2.0");
+    ///     }
+    ///     writeln!(task, "done");
+    /// }
+    /// ```
+    pub fn section(&self, title: impl Display) -> Section<'_> {
+        let _ = self.apply(|output| output.begin_section(&title));
+        Section { task: self }
+    }
+
+    /// Head this task's output with a title, such as the name of the thing
+    /// it works on.
+    ///
+    /// The title is written right away as a bold line of its own, so that
+    /// every task's output begins with a header styled the same way. With
+    /// [`Sequencer::title_footers`], the task's output also ends with a line
+    /// repeating the title along with whether the task failed.
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn work(task: Task, package: &str) {
+    ///     task.set_title(format_args!("compiling {}", package));
+    ///     writeln!(task, "...");
+    /// }
+    /// ```
+    ///
+    /// [`Sequencer::title_footers`]: crate::Sequencer::title_footers
+    pub fn set_title(&self, title: impl Display) {
+        let title = title.to_string();
+        {
+            let inner = &mut *self.handle.inner.lock();
+            inner.get(self.handle.index).title = Some(title.clone());
+        }
+        let _ = self.apply(|output| output.title_line(&title));
+    }
+
+    /// Begin a block of this task's output that is printed all at once.
+    ///
+    /// Ordinarily the task printing in real time has each write printed as
+    /// soon as it happens, so a block of lines built up over several writes
+    /// can have other text such as a [status footer] show up in the middle
+    /// of it. Output written while the returned guard is alive is instead
+    /// held back, and printed in one piece when the guard is dropped. Blocks
+    /// may be nested, in which case the output is printed when the outermost
+    /// one ends.
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn work(task: Task, errors: &[String]) {
+    ///     let _atomic = task.atomic();
+    ///     writeln!(task, "{} errors:", errors.len());
+    ///     for error in errors {
+    ///         writeln!(task, "  {}", error);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [status footer]: crate::Sequencer::status_footer
+    pub fn atomic(&self) -> Atomic<'_> {
+        let _ = self.apply(|output| {
+            output.atomic += 1;
+            Ok(())
+        });
+        Atomic { task: self }
+    }
+
+    /// Writer for this task's output stream. This is the same as writing to
+    /// the task itself, and exists for symmetry with [`err`][Task::err].
+    pub fn out(&self) -> TaskWriter<'_> {
+        TaskWriter {
+            task: self,
+            err: false,
+        }
+    }
+
+    /// Writer for this task's error stream, for diagnostics as opposed to the
+    /// task's data.
+    ///
+    /// For a Sequencer whose output goes to stdout, what is written here goes
+    /// to stderr instead. For every other Sequencer it goes to the same place
+    /// as the rest of the output. Either way it is sequenced together with
+    /// the task's output stream: what the task writes to the two streams is
+    /// printed in the order it was written, after everything of the tasks
+    /// before it and before anything of the tasks after it.
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn work(task: Task, path: &str) {
+    ///     writeln!(task.err(), "reading {}", path);
+    ///     writeln!(task.out(), "{} lines", 42);
+    /// }
+    /// ```
+    pub fn err(&self) -> TaskWriter<'_> {
+        TaskWriter {
+            task: self,
+            err: true,
+        }
+    }
+
+    /// Mark this task as failed.
+    ///
+    /// This is visible to [`Sequencer::route`] predicates and hooks through
+    /// [`TaskInfo::failed`], and determines which tasks get printed under
+    /// [`Sequencer::quiet_unless_failed`].
+    ///
+    /// [`Sequencer::route`]: crate::Sequencer::route
+    /// [`Sequencer::quiet_unless_failed`]: crate::Sequencer::quiet_unless_failed
+    /// [`TaskInfo::failed`]: crate::TaskInfo::failed
+    pub fn set_failed(&self) {
+        let inner = &mut *self.handle.inner.lock();
+        inner.get(self.handle.index).failed = true;
+    }
+
+    /// Whether the run this task belongs to has been called off by
+    /// [`Sequencer::cancel_all`], or this task has run for longer than
+    /// [`Sequencer::timeout`] allows.
+    ///
+    /// Long-running work can check this between steps, to stop early rather
+    /// than finishing work whose result nobody is waiting for.
+    ///
+    /// ```
+    /// use oqueue::Sequencer;
+    ///
+    /// let oqueue = Sequencer::stderr();
+    /// let task = oqueue.begin();
+    /// for step in 0.. {
+    ///     if task.is_cancelled() {
+    ///         writeln!(task, "cancelled after {} steps", step);
+    ///         break;
+    ///     }
+    ///     # if step == 3 { oqueue.cancel_all(); }
+    /// }
+    /// ```
+    ///
+    /// [`Sequencer::cancel_all`]: crate::Sequencer::cancel_all
+    /// [`Sequencer::timeout`]: crate::Sequencer::timeout
+    pub fn is_cancelled(&self) -> bool {
+        let inner = &mut *self.handle.inner.lock();
+        inner.cancelled || inner.get(self.handle.index).timed_out
+    }
+
+    pub(crate) fn captures_panics(&self) -> bool {
+        self.handle.capture_panics
+    }
+
+    /// Writes the message of a panic that happened while this task was
+    /// current, and marks the task failed.
+    pub(crate) fn panicked(&self, panic: &Panic) {
+        let line = {
+            let inner = &mut *self.handle.inner.lock();
+            inner.get(self.handle.index).failed = true;
+            let location = panic.location.as_deref();
+            let line = inner.messages.lock().panicked(&panic.message, location);
+            line
+        };
+        let _ = self.apply(|output| output.panicked(&line));
+    }
+
+    /// Mark this task as successful, undoing any earlier
+    /// [`set_failed`][Task::set_failed]. Tasks are considered successful
+    /// unless marked otherwise.
+    pub fn succeed(&self) {
+        let inner = &mut *self.handle.inner.lock();
+        inner.get(self.handle.index).failed = false;
+    }
+
+    /// Finish this task without printing the output it has buffered.
+    ///
+    /// This is for a task that discovers partway through that its output is
+    /// not relevant, for example because a cached result was found. Anything
+    /// written afterward through other clones of the task is discarded too.
+    /// Output that was already printed in real time cannot be taken back.
+    ///
+    /// ```
+    /// use oqueue::Task;
+    ///
+    /// fn work(task: Task, cached: bool) {
+    ///     writeln!(task, "computing...");
+    ///     if cached {
+    ///         task.cancel();
+    ///         return;
+    ///     }
+    ///     writeln!(task, "done");
+    /// }
+    /// ```
+    pub fn cancel(self) {
+        let inner = &mut *self.handle.inner.lock();
+        let output = inner.get(self.handle.index);
+        let len = output.clear();
+        output.discarded = true;
+        inner.buffered = inner.buffered.saturating_sub(len);
+    }
+
+    /// Run a child process, writing its stdout and stderr to this task as
+    /// they are produced, and wait for it to exit.
+    ///
+    /// The command's stdout and stderr are replaced by pipes. Output is
+    /// transferred a line at a time, so that a line from one stream is never
+    /// split by one from the other. If this is the task printing in real
+    /// time, each line shows up as soon as the child writes it.
+    ///
+    /// ```
+    /// use oqueue::Task;
+    /// use std::io;
+    /// use std::process::Command;
+    ///
+    /// fn work(task: Task) -> io::Result<()> {
+    ///     let status = task.run_command(Command::new("cargo").arg("build"))?;
+    ///     if !status.success() {
+    ///         task.set_failed();
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the child could not be spawned, or if reading its
+    /// output or waiting for it fails.
+    pub fn run_command(&self, command: &mut Command) -> Result<ExitStatus> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stderr = child.stderr.take().map(|stderr| {
+            let task = self.clone();
+            thread::spawn(move || task.copy_lines(stderr))
+        });
+        let copied = match child.stdout.take() {
+            Some(stdout) => self.copy_lines(stdout),
+            None => Ok(()),
+        };
+        let copied_stderr = match stderr {
+            Some(thread) => thread.join().unwrap_or(Ok(())),
+            None => Ok(()),
+        };
+        let status = child.wait()?;
+        copied?;
+        copied_stderr?;
+        Ok(status)
+    }
+
+    /// Run a child process under a pseudo-terminal, writing its output to
+    /// this task as it is produced, and wait for it to exit.
+    ///
+    /// Unlike [`run_command`][Task::run_command], where the child sees that
+    /// its output is a pipe, programs run this way behave as they would in a
+    /// terminal, so compilers and test runners keep their colored output.
+    /// The color is kept or removed according to the color settings of the
+    /// task, the same as for anything else written to it. Line endings are
+    /// converted from the terminal's `\r\n` to `\n`. The child's stdout and
+    /// stderr are both connected to the same terminal, so they arrive as a
+    /// single stream.
+    ///
+    /// On Windows the child runs under ConPTY. The escape sequences that
+    /// ConPTY adds for redrawing the console, such as to move the cursor or
+    /// set the window title, are left out, keeping only colors.
+    ///
+    /// ```no_run
+    /// use oqueue::Task;
+    /// use portable_pty::CommandBuilder;
+    /// use std::io;
+    ///
+    /// fn work(task: Task) -> io::Result<()> {
+    ///     let mut command = CommandBuilder::new("cargo");
+    ///     command.arg("test");
+    ///     let status = task.run_pty(command)?;
+    ///     if !status.success() {
+    ///         task.set_failed();
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pseudo-terminal could not be opened, if the
+    /// child could not be spawned, or if waiting for it fails.
+    #[cfg(feature = "portable-pty")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "portable-pty")))]
+    pub fn run_pty(
+        &self,
+        command: portable_pty::CommandBuilder,
+    ) -> Result<portable_pty::ExitStatus> {
+        use portable_pty::PtySize;
+
+        let pair = portable_pty::native_pty_system()
+            .openpty(PtySize::default())
+            .map_err(pty_error)?;
+        let reader = pair.master.try_clone_reader().map_err(pty_error)?;
+        let mut child = pair.slave.spawn_command(command).map_err(pty_error)?;
+        // Otherwise the terminal stays open after the child exits, and the
+        // output never ends.
+        drop(pair.slave);
+
+        let task = self.clone();
+        let copier = thread::spawn(move || task.copy_pty(reader));
+        let status = child.wait();
+        // Under ConPTY, the output does not end until the pseudoconsole is
+        // closed, which first passes along whatever the child wrote last.
+        drop(pair.master);
+        let _ = copier.join();
+        status
+    }
+
+    #[cfg(feature = "portable-pty")]
+    fn copy_pty(&self, mut reader: Box<dyn Read + Send>) {
+        use crate::ansi::Sanitizer;
+        use std::io::ErrorKind;
+
+        // ConPTY adds escape sequences of its own for redrawing the console,
+        // which would be garbage anywhere else.
+        let mut sanitizer = if cfg!(windows) {
+            Some(Sanitizer::new())
+        } else {
+            None
+        };
+        let mut chunk = [0; 4096];
+        let mut carriage_return = false;
+        loop {
+            let len = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                // Reading fails with EIO instead of reaching the end once the
+                // child has exited.
+                Err(_) => break,
+            };
+            let mut clean = Vec::new();
+            let bytes = match &mut sanitizer {
+                Some(sanitizer) => {
+                    sanitizer.sanitize(&chunk[..len], &mut clean);
+                    &clean
+                }
+                None => &chunk[..len],
+            };
+            let mut text = Vec::with_capacity(bytes.len() + 1);
+            for &b in bytes {
+                if carriage_return && b != b'\n' {
+                    text.push(b'\r');
+                }
+                carriage_return = b == b'\r';
+                if !carriage_return {
+                    text.push(b);
+                }
+            }
+            let _ = self.apply(|output| output.write_all(&text));
+        }
+        if carriage_return {
+            let _ = self.apply(|output| output.write_all(b"\r"));
+        }
+    }
+
+    fn copy_lines(&self, stream: impl Read) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                return Ok(());
+            }
+            // Keep draining the pipe even if printing fails, so that the
+            // child does not get stuck on a full pipe.
+            let _ = self.apply(|output| output.write_all(&line));
+        }
+    }
+
     #[doc(hidden)]
     pub fn write_fmt(&self, args: fmt::Arguments) {
         let _ = self.apply(|w| w.write_fmt(args));
     }
 
-    fn apply<T>(&self, f: impl FnOnce(&mut dyn WriteColor) -> T) -> T {
-        let inner = &mut *self.handle.inner.lock();
+    fn apply<T>(&self, f: impl FnOnce(&mut Output) -> Result<T>) -> Result<T> {
+        self.handle.apply(f)
+    }
+}
 
-        if self.handle.index == inner.finished {
-            f(&mut inner.stream)
+/// Applies `f` to the output of the task with the given index, written to
+/// either its output stream or its error stream, then takes care of whatever
+/// needs to happen to what got written, such as printing it right away.
+pub(super) fn apply<T>(
+    inner: &mut Inner,
+    index: usize,
+    err: bool,
+    f: impl FnOnce(&mut Output) -> Result<T>,
+) -> Result<T> {
+    if inner.get(index).err != err {
+        if inner.interleaved {
+            // Finish the line of the other stream, which is going to a
+            // different place.
+            let lines = inner.take_lines(index, !err, true);
+            inner.stream(!err, lines);
+        }
+        let default = inner.blank_for(err).clone();
+        inner.get(index).switch(err, &default);
+    }
+
+    if let Some(folding) = inner.folding {
+        let output = inner.get(index);
+        if matches!(output.fold, Fold::Unopened) && !output.discarded {
+            let title = match &output.title {
+                Some(title) => title.clone(),
+                None => inner.messages.lock().fold_title(index),
+            };
+            if !inner.interleaved && inner.report.is_none() {
+                inner.get(index).open_fold(folding, &title)?;
+            }
+        }
+    }
+
+    if inner.collapse_progress {
+        inner.get(index).collapse = !inner.is_realtime(index);
+    }
+
+    let closed = inner.closed;
+    let output = inner.get(index);
+    let before = output.buffer.len();
+    let result = f(output)?;
+    if output.discarded || closed {
+        output.buffer.clear();
+        inner.buffered = inner.buffered.saturating_sub(before);
+        #[cfg(feature = "metrics")]
+        inner.report_buffered();
+        return Ok(result);
+    }
+    written(inner, index, err, before)?;
+
+    let holding = inner.get(index).atomic > 0;
+    if inner.interleaved {
+        if holding {
+            return Ok(result);
+        }
+        let lines = inner.take_lines(index, err, false);
+        inner.stream(err, lines);
+    } else if !holding && inner.is_realtime(index) {
+        if inner.footer.is_some() && inner.live != Some(index) {
+            inner.live = Some(index);
+            inner.refresh_footer();
+        }
+        // Printed by emit_ready once the lock is released, as the Sink may
+        // block for as long as it takes the destination to catch up.
+        let sink = Arc::clone(&inner.sink);
+        let err_sink = Arc::clone(inner.sink_for(true));
+        let blank = inner.blank.clone();
+        let err_blank = inner.blank_for(true).clone();
+        let head = inner.get(index);
+        let mut streamed = Vec::new();
+        let mut streamed_len = before;
+        if let Some(spill) = head.spill.take() {
+            streamed.push(Detached {
+                sink: Arc::clone(&sink),
+                spill: Some(spill),
+                buffer: blank.clone(),
+            });
+        }
+        for segment in head.earlier.drain(..) {
+            streamed_len += segment.buffer.len();
+            let sink = if segment.err { &err_sink } else { &sink };
+            streamed.push(Detached {
+                sink: Arc::clone(sink),
+                spill: None,
+                buffer: segment.buffer,
+            });
+        }
+        let (sink, blank) = if head.err {
+            (err_sink, err_blank)
         } else {
-            f(&mut inner.get(self.handle.index).buffer)
+            (sink, blank)
+        };
+        streamed.push(Detached {
+            sink,
+            spill: None,
+            buffer: mem::replace(&mut head.buffer, blank),
+        });
+        inner.streamed.extend(streamed);
+        inner.buffered = inner.buffered.saturating_sub(streamed_len);
+    } else {
+        let after = inner.get(index).buffer.len();
+        inner.buffered = (inner.buffered + after).saturating_sub(before);
+        if let Some(tail) = inner.tail {
+            let freed = inner.get(index).keep_tail(tail);
+            inner.buffered = inner.buffered.saturating_sub(freed);
+        } else if let Some(limit) = inner.spill_limit {
+            if inner.buffered > limit {
+                let default = inner.blank.clone();
+                let freed = inner.get(index).spill(&default);
+                inner.buffered = inner.buffered.saturating_sub(freed);
+            }
+        }
+        inner.peak_buffered = inner.peak_buffered.max(inner.buffered);
+        #[cfg(feature = "metrics")]
+        inner.report_buffered();
+    }
+
+    Ok(result)
+}
+
+/// Takes note of what was just written to the task's buffer, from offset
+/// `before` on.
+fn written(inner: &mut Inner, index: usize, err: bool, before: usize) -> Result<()> {
+    let written = inner.get(index).buffer.len().saturating_sub(before);
+    inner.written += written as u64;
+    if let (Some(events), true) = (&mut inner.events, written > 0) {
+        events.output(index, err, written);
+    }
+    let record = inner.keeps_transcripts();
+    let output = inner.get(index);
+    if record {
+        if let Some(written) = output.buffer.as_slice().get(before..) {
+            output.transcript.extend_from_slice(written);
+        }
+    }
+    if let Some(elision) = &mut output.elision {
+        if let Some(written) = output.buffer.as_slice().get(before..) {
+            let len = elision.hold(written);
+            if len < written.len() {
+                let kept = output.buffer.as_slice()[..before + len].to_vec();
+                output.buffer.clear();
+                output.buffer.write_all(&kept)?;
+            }
         }
     }
+    Ok(())
+}
+
+#[cfg(feature = "portable-pty")]
+fn pty_error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, error)
+}
+
+impl Handle {
+    pub(super) fn new(index: usize, inner: Arc<Mutex<Inner>>, capture_panics: bool) -> Self {
+        Handle {
+            inner,
+            index,
+            begun: Instant::now(),
+            capture_panics,
+        }
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Performs a write to the task's output, then prints it right away if
+    /// this is the task printing in real time.
+    pub(crate) fn apply<T>(&self, f: impl FnOnce(&mut Output) -> Result<T>) -> Result<T> {
+        self.apply_to(false, f)
+    }
+
+    /// Same as `apply`, but for either the task's output stream or its error
+    /// stream.
+    fn apply_to<T>(&self, err: bool, f: impl FnOnce(&mut Output) -> Result<T>) -> Result<T> {
+        let mut inner = self.inner.lock();
+        let result = apply(&mut inner, self.index, err, f);
+        drop(emit_ready(&self.inner, inner));
+        result
+    }
+
+    /// Flushes the destination of the task's output stream, or of its error
+    /// stream, if this is the task printing in real time. The sink is flushed
+    /// after releasing the lock, as that may take as long as a print.
+    fn flush(&self, err: bool) -> Result<()> {
+        let sink = {
+            let inner = &mut *self.inner.lock();
+            if !inner.is_realtime(self.index) {
+                return Ok(());
+            }
+            Arc::clone(inner.sink_for(err))
+        };
+        let flushed = sink.lock().flush();
+        flushed
+    }
+
+    /// Tells the reports of the run that the task is finished.
+    fn report(&self, inner: &mut Inner) {
+        if inner.events.is_some() {
+            let output = inner.get(self.index);
+            let (bytes, elapsed, failed) = (output.bytes, output.elapsed, output.failed);
+            if let Some(events) = &mut inner.events {
+                events.finished(self.index, bytes, elapsed, failed);
+            }
+        }
+        if inner.keeps_transcripts() {
+            let output = inner.get(self.index);
+            let title = output.title.clone();
+            let titled = title.is_some();
+            let mut case = Case {
+                index: self.index,
+                name: String::new(),
+                elapsed: output.elapsed,
+                failed: output.failed,
+                skipped: output.discarded,
+                output: mem::take(&mut output.transcript),
+            };
+            case.name = title.unwrap_or_else(|| inner.messages.lock().fold_title(self.index));
+            if let Some(archive) = &mut inner.archive {
+                let archived = archive.record(case.clone(), titled);
+                inner.record(archived);
+            }
+            if let Some(html) = &mut inner.html {
+                html.record(case.clone());
+            }
+            if let Some(junit) = &mut inner.junit {
+                junit.record(case);
+            }
+        }
+    }
+}
+
+/// One of the two streams of a task's output, obtained from [`Task::out`] or
+/// [`Task::err`].
+pub struct TaskWriter<'a> {
+    task: &'a Task,
+    err: bool,
+}
+
+impl TaskWriter<'_> {
+    #[doc(hidden)]
+    pub fn write_fmt(&self, args: fmt::Arguments) {
+        let _ = self.apply(|w| w.write_fmt(args));
+    }
+
+    fn apply<T>(&self, f: impl FnOnce(&mut Output) -> Result<T>) -> Result<T> {
+        self.task.handle.apply_to(self.err, f)
+    }
+
+    /// Empty buffer of the kind this stream's output is written into.
+    fn blank(&self) -> Buffer {
+        let inner = &mut *self.task.handle.inner.lock();
+        let default = inner.blank_for(self.err).clone();
+        inner.get(self.task.index).blank(&default)
+    }
+}
+
+impl Write for TaskWriter<'_> {
+    fn write(&mut self, b: &[u8]) -> Result<usize> {
+        self.apply(|w| w.write(b))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.task.handle.flush(self.err)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.apply(|w| w.write_all(buf))
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> Result<()> {
+        self.apply(|w| w.write_fmt(args))
+    }
+}
+
+impl WriteColor for TaskWriter<'_> {
+    fn supports_color(&self) -> bool {
+        self.blank().supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> Result<()> {
+        self.apply(|w| w.set_color(spec))
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.apply(|w| w.reset())
+    }
+
+    fn supports_hyperlinks(&self) -> bool {
+        self.blank().supports_hyperlinks()
+    }
+
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> Result<()> {
+        self.apply(|w| w.set_hyperlink(link))
+    }
+}
+
+impl Debug for TaskWriter<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.err { "err" } else { "out" };
+        formatter
+            .debug_tuple("TaskWriter")
+            .field(&self.task.index)
+            .field(&name)
+            .finish()
+    }
+}
+
+/// Section of a task's output, begun by [`Task::section`].
+///
+/// The section ends when this guard is dropped.
+#[must_use = "the section ends immediately if the guard is not held"]
+pub struct Section<'a> {
+    task: &'a Task,
+}
+
+impl Drop for Section<'_> {
+    fn drop(&mut self) {
+        let _ = self.task.apply(|output| output.end_section());
+    }
+}
+
+/// Block of a task's output that is printed all at once, begun by
+/// [`Task::atomic`].
+///
+/// The output is printed when this guard is dropped.
+#[must_use = "the block ends immediately if the guard is not held"]
+pub struct Atomic<'a> {
+    task: &'a Task,
+}
+
+impl Drop for Atomic<'_> {
+    fn drop(&mut self) {
+        let _ = self.task.apply(|output| {
+            output.atomic -= 1;
+            Ok(())
+        });
+    }
 }
 
 impl Write for Task {
@@ -109,7 +1077,7 @@ impl Write for Task {
     }
 
     fn flush(&mut self) -> Result<()> {
-        self.apply(|w| w.flush())
+        self.handle.flush(false)
     }
 
     fn write_all(&mut self, buf: &[u8]) -> Result<()> {
@@ -123,7 +1091,8 @@ impl Write for Task {
 
 impl WriteColor for Task {
     fn supports_color(&self) -> bool {
-        self.apply(|w| w.supports_color())
+        let inner = &mut *self.handle.inner.lock();
+        inner.get(self.handle.index).buffer.supports_color()
     }
 
     fn set_color(&mut self, spec: &ColorSpec) -> Result<()> {
@@ -133,24 +1102,116 @@ impl WriteColor for Task {
     fn reset(&mut self) -> Result<()> {
         self.apply(|w| w.reset())
     }
+
+    fn supports_hyperlinks(&self) -> bool {
+        let inner = &mut *self.handle.inner.lock();
+        inner.get(self.handle.index).supports_hyperlinks()
+    }
+
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> Result<()> {
+        self.apply(|w| w.set_hyperlink(link))
+    }
 }
 
 impl Drop for Handle {
     fn drop(&mut self) {
-        let inner = &mut *self.inner.lock();
+        let _ = self.apply(|output| output.commit_progress());
+        let _ = self.apply(|output| output.end_repeats());
+        let _ = self.apply(|output| output.end_limits());
+        let unicode = self.inner.lock().unicode;
+        let _ = self.apply(|output| output.end_elision(unicode));
+        let (footer, duration) = {
+            let inner = &mut *self.inner.lock();
+            let output = inner.get(self.index);
+            let failed = output.failed;
+            let footer = match output.title.clone() {
+                Some(title) if inner.title_footers => {
+                    Some(inner.messages.lock().title_footer(&title, failed))
+                }
+                _ => None,
+            };
+            let duration = if inner.durations {
+                Some(inner.messages.lock().duration(self.begun.elapsed()))
+            } else {
+                None
+            };
+            (footer, duration)
+        };
+        if let Some(line) = footer {
+            let _ = self.apply(|output| output.title_footer(&line));
+        }
+        if let Some(line) = duration {
+            let _ = self.apply(|output| output.duration(&line));
+        }
+        let open = {
+            let inner = &mut *self.inner.lock();
+            let output = inner.get(self.index);
+            if let Fold::Open(..) = output.fold {
+                true
+            } else {
+                output.fold = Fold::Closed;
+                false
+            }
+        };
+        if open {
+            let _ = self.apply(|output| output.close_fold());
+        }
 
-        inner.get(self.index).done = true;
+        let mut inner = self.inner.lock();
 
-        while inner.pending.front().map_or(false, Output::is_done) {
-            inner.finished += 1;
-            let mut task = inner.pending.pop_front().unwrap();
-            let _ = task.buffer.reset();
-            let _ = inner.writer.print(&task.buffer);
+        let failed = inner.get(self.index).failed;
+        let thread = inner.tally.record(failed);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &inner.metrics {
+            metrics.finished(failed);
+        }
+        if inner.live == Some(self.index) {
+            inner.live = None;
+        }
+        inner.refresh_footer();
+        let err = inner.get(self.index).err;
+        if inner.interleaved {
+            let lines = inner.take_lines(self.index, err, true);
+            inner.stream(err, lines);
+        } else {
+            let output = inner.get(self.index);
+            if !output.discarded && !matches!(output.fold, Fold::Closed) {
+                let _ = output.buffer.reset();
+            }
+        }
+        let output = inner.get(self.index);
+        output.done = true;
+        output.thread = thread;
+        output.elapsed = self.begun.elapsed();
+        #[cfg(feature = "opentelemetry")]
+        if let Some(span) = output.span.take() {
+            let title = output.title.as_deref();
+            span.end(title, output.failed, output.discarded, output.bytes);
+        }
+        self.report(&mut inner);
+        let complete = if inner.hooks.complete.is_empty() {
+            None
+        } else {
+            let info = inner.get(self.index).info();
+            Some((inner.hooks.complete.clone(), info))
+        };
+
+        if inner.completion_order {
+            inner.completed.push_back(self.index);
+            if inner.claimed == Some(self.index) {
+                inner.claimed = None;
+            }
         }
 
-        if let Some(head) = inner.pending.get_mut(0) {
-            let _ = inner.writer.print(&head.buffer);
-            head.buffer.clear();
+        if let Some((hooks, info)) = complete {
+            drop(inner);
+            for hook in hooks {
+                hook(&info);
+            }
+            inner = self.inner.lock();
         }
+
+        inner.redraw_footer();
+        drop(super::emit_ready(&self.inner, inner));
     }
 }
@@ -0,0 +1,69 @@
+use metrics::{counter, gauge, histogram, Counter, Gauge, Histogram};
+use std::time::Duration;
+
+/// Reports what a Sequencer is doing to the recorder installed for the
+/// `metrics` crate, under [`Sequencer::export_metrics`].
+///
+/// [`Sequencer::export_metrics`]: crate::Sequencer::export_metrics
+pub(crate) struct Metrics {
+    depth: Gauge,
+    buffered: Gauge,
+    passed: Counter,
+    failed: Counter,
+    wait: Histogram,
+    /// Amount of buffered output as of the last report, so that several
+    /// Sequencers add up to the total.
+    reported: usize,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        Metrics {
+            depth: gauge!("oqueue_queue_depth"),
+            buffered: gauge!("oqueue_buffered_bytes"),
+            passed: counter!("oqueue_tasks_completed", "status" => "passed"),
+            failed: counter!("oqueue_tasks_completed", "status" => "failed"),
+            wait: histogram!("oqueue_head_of_line_wait_seconds"),
+            reported: 0,
+        }
+    }
+
+    /// Notes that a task was begun.
+    pub(crate) fn begun(&self) {
+        self.depth.increment(1.0);
+    }
+
+    /// Notes that a task finished running.
+    pub(crate) fn finished(&self, failed: bool) {
+        if failed {
+            self.failed.increment(1);
+        } else {
+            self.passed.increment(1);
+        }
+    }
+
+    /// Notes that all the output of a task has been taken for printing, which
+    /// was held up for `waited` after the task finished by the tasks ahead of
+    /// it.
+    pub(crate) fn printed(&self, waited: Duration) {
+        self.depth.decrement(1.0);
+        self.wait.record(waited.as_secs_f64());
+    }
+
+    /// Notes how much output is now held in memory.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn buffered(&mut self, bytes: usize) {
+        if bytes > self.reported {
+            self.buffered.increment((bytes - self.reported) as f64);
+        } else if bytes < self.reported {
+            self.buffered.decrement((self.reported - bytes) as f64);
+        }
+        self.reported = bytes;
+    }
+}
+
+impl Drop for Metrics {
+    fn drop(&mut self) {
+        self.buffered(0);
+    }
+}
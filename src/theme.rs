@@ -0,0 +1,146 @@
+use crate::style::ToColorSpec;
+use std::env;
+use termcolor::{Color, ColorSpec};
+
+/// Kind of diagnostic written by [`Task::error`][crate::Task::error] and
+/// friends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Note,
+}
+
+/// Styles of the labels that begin diagnostics, such as the `error:` of
+/// [`Task::error`][crate::Task::error].
+///
+/// Installed on a Sequencer using [`Sequencer::theme`], so that every task
+/// formats its diagnostics the same way. The wording of the labels comes
+/// from [`Messages::label`].
+///
+/// ```
+/// use oqueue::{Color, Sequencer, Theme};
+///
+/// let theme = Theme::new().note(Color::Magenta);
+/// let oqueue = Sequencer::stderr().theme(theme);
+/// ```
+///
+/// [`Sequencer::theme`]: crate::Sequencer::theme
+/// [`Messages::label`]: crate::Messages::label
+#[derive(Clone, Debug)]
+pub struct Theme {
+    error: ColorSpec,
+    warning: ColorSpec,
+    info: ColorSpec,
+    note: ColorSpec,
+    ok: ColorSpec,
+    fail: ColorSpec,
+}
+
+#[allow(clippy::needless_pass_by_value)]
+impl Theme {
+    /// The default theme: errors in bold red, warnings in bold yellow, info
+    /// in bold green and notes in bold cyan. Status markers are green for
+    /// success and red for failure.
+    pub fn new() -> Self {
+        let bold = |color| {
+            let mut spec = ColorSpec::new();
+            spec.set_bold(true).set_fg(Some(color));
+            spec
+        };
+        Theme {
+            error: bold(Color::Red),
+            warning: bold(Color::Yellow),
+            info: bold(Color::Green),
+            note: bold(Color::Cyan),
+            ok: Color::Green.to_color_spec(),
+            fail: Color::Red.to_color_spec(),
+        }
+    }
+
+    /// Style of the label of [`Task::error`][crate::Task::error].
+    #[must_use]
+    pub fn error(mut self, style: impl ToColorSpec) -> Self {
+        self.error = style.to_color_spec();
+        self
+    }
+
+    /// Style of the label of [`Task::warn`][crate::Task::warn].
+    #[must_use]
+    pub fn warning(mut self, style: impl ToColorSpec) -> Self {
+        self.warning = style.to_color_spec();
+        self
+    }
+
+    /// Style of the label of [`Task::info`][crate::Task::info].
+    #[must_use]
+    pub fn info(mut self, style: impl ToColorSpec) -> Self {
+        self.info = style.to_color_spec();
+        self
+    }
+
+    /// Style of the label of [`Task::note`][crate::Task::note].
+    #[must_use]
+    pub fn note(mut self, style: impl ToColorSpec) -> Self {
+        self.note = style.to_color_spec();
+        self
+    }
+
+    /// Style of the marker of [`Task::status_ok`][crate::Task::status_ok].
+    #[must_use]
+    pub fn ok(mut self, style: impl ToColorSpec) -> Self {
+        self.ok = style.to_color_spec();
+        self
+    }
+
+    /// Style of the marker of
+    /// [`Task::status_fail`][crate::Task::status_fail].
+    #[must_use]
+    pub fn fail(mut self, style: impl ToColorSpec) -> Self {
+        self.fail = style.to_color_spec();
+        self
+    }
+
+    pub(crate) fn status(&self, ok: bool) -> &ColorSpec {
+        if ok {
+            &self.ok
+        } else {
+            &self.fail
+        }
+    }
+
+    pub(crate) fn style(&self, severity: Severity) -> &ColorSpec {
+        match severity {
+            Severity::Error => &self.error,
+            Severity::Warning => &self.warning,
+            Severity::Info => &self.info,
+            Severity::Note => &self.note,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::new()
+    }
+}
+
+/// Guesses from the locale whether the terminal can render characters beyond
+/// ASCII, such as the ✓ of a status marker.
+pub(crate) fn unicode() -> bool {
+    if cfg!(windows) {
+        // Windows Terminal, as opposed to the legacy console.
+        return env::var_os("WT_SESSION").is_some();
+    }
+    for var in &["LC_ALL", "LC_CTYPE", "LANG"] {
+        match env::var(var) {
+            Ok(locale) if !locale.is_empty() => {
+                let locale = locale.to_ascii_lowercase();
+                return locale.contains("utf-8") || locale.contains("utf8");
+            }
+            _ => {}
+        }
+    }
+    false
+}
@@ -0,0 +1,84 @@
+use std::io::{Result, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Kind of timestamp put at the start of every line by
+/// [`Sequencer::timestamps`][crate::Sequencer::timestamps].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timestamps {
+    /// Time of day in UTC, in RFC 3339 format: `2024-05-01T12:34:56.789Z`.
+    Rfc3339,
+    /// Time since timestamps were turned on, which is usually when the
+    /// Sequencer was made: `[  12.345s]`.
+    Elapsed,
+}
+
+/// Source of the timestamps written by a Sequencer.
+#[derive(Copy, Clone)]
+pub(crate) struct Clock {
+    format: Timestamps,
+    start: Instant,
+}
+
+impl Clock {
+    pub(crate) fn new(format: Timestamps) -> Self {
+        Clock {
+            format,
+            start: Instant::now(),
+        }
+    }
+
+    /// Writes the current time, followed by a space.
+    pub(crate) fn write(&self, w: &mut impl Write) -> Result<()> {
+        match self.format {
+            Timestamps::Rfc3339 => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                let secs = now.as_secs();
+                let (year, month, day) = civil(secs / 86_400);
+                let time = secs % 86_400;
+                write!(
+                    w,
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z ",
+                    year,
+                    month,
+                    day,
+                    time / 3600,
+                    time / 60 % 60,
+                    time % 60,
+                    now.subsec_millis(),
+                )
+            }
+            Timestamps::Elapsed => {
+                let elapsed = self.start.elapsed();
+                write!(
+                    w,
+                    "[{:>4}.{:03}s] ",
+                    elapsed.as_secs(),
+                    elapsed.subsec_millis(),
+                )
+            }
+        }
+    }
+}
+
+/// Converts a number of days since 1970-01-01 to year, month and day.
+///
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
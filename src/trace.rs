@@ -0,0 +1,132 @@
+use crate::Task;
+use std::cell::RefCell;
+use std::fmt::{Debug, Write as _};
+use std::io::{self, Write};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Span, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+thread_local! {
+    /// Task waiting to be attached to the span being created by Task::span.
+    static ATTACHING: RefCell<Option<Task>> = RefCell::new(None);
+}
+
+/// Layer for `tracing-subscriber` that writes events into the task whose
+/// [span][Task::span] they occur in.
+///
+/// Libraries that log using `tracing` from inside a task then have their
+/// output sequenced along with everything else the task writes, instead of
+/// interleaving with other tasks on stderr. Events outside of any task's span
+/// are printed to stderr directly.
+///
+/// ```
+/// use oqueue::{Sequencer, TaskLayer};
+/// use tracing_subscriber::layer::SubscriberExt as _;
+///
+/// let subscriber = tracing_subscriber::registry().with(TaskLayer::new());
+/// tracing::subscriber::set_global_default(subscriber).unwrap();
+///
+/// let oqueue = Sequencer::stderr();
+/// let task = oqueue.begin();
+/// let _span = task.span().entered();
+/// tracing::info!(attempt = 1, "connecting");
+/// ```
+#[derive(Debug, Default)]
+pub struct TaskLayer {
+    _private: (),
+}
+
+impl TaskLayer {
+    pub fn new() -> Self {
+        TaskLayer { _private: () }
+    }
+}
+
+impl Task {
+    /// Make a span within which `tracing` events are written to this task
+    /// by a [`TaskLayer`].
+    ///
+    /// The span is named `oqueue_task`, has target `oqueue` and level INFO,
+    /// and records the task's index as a field `index`. Events are routed
+    /// from inside any span nested within it too.
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(all(feature = "tracing", feature = "tracing-subscriber")))
+    )]
+    pub fn span(&self) -> Span {
+        ATTACHING.with(|attaching| *attaching.borrow_mut() = Some(self.clone()));
+        let span = tracing::info_span!(target: "oqueue", "oqueue_task", index = self.index);
+        // The span may have been disabled by a filter, in which case no layer
+        // got to see it.
+        ATTACHING.with(|attaching| attaching.borrow_mut().take());
+        span
+    }
+}
+
+impl<S> Layer<S> for TaskLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        if let Some(task) = ATTACHING.with(|attaching| attaching.borrow_mut().take()) {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(task);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event, ctx: Context<S>) {
+        let metadata = event.metadata();
+        let mut line = Line {
+            message: String::new(),
+            fields: String::new(),
+        };
+        event.record(&mut line);
+        let line = format!(
+            "{:>5} {}: {}{}\n",
+            metadata.level(),
+            metadata.target(),
+            line.message,
+            line.fields,
+        );
+
+        let task = ctx.event_scope(event).and_then(|scope| {
+            scope
+                .into_iter()
+                .find_map(|span| span.extensions().get::<Task>().cloned())
+        });
+        match task {
+            Some(mut task) => {
+                let _ = task.write_all(line.as_bytes());
+            }
+            None => {
+                let _ = io::stderr().write_all(line.as_bytes());
+            }
+        }
+    }
+}
+
+struct Line {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Line {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+const ESC: u8 = b'\x1b';
+
+/// Longest prefix of `output` that is at most `max` bytes long and does not
+/// end in the middle of a character or of an escape sequence.
+///
+/// This is for cutting short the output of a task, which may hold UTF-8 text
+/// colored by escape sequences, without leaving a partial character or a
+/// broken escape sequence at the end. Bytes that are not valid UTF-8 count as
+/// characters of their own.
+///
+/// ```
+/// let output = "caf\u{e9} \x1b[31mred\x1b[0m".as_bytes();
+///
+/// assert_eq!(oqueue::truncate(output, 4), b"caf");
+/// assert_eq!(oqueue::truncate(output, 8), "caf\u{e9} ".as_bytes());
+/// assert_eq!(oqueue::truncate(output, 11), "caf\u{e9} \x1b[31m".as_bytes());
+/// ```
+pub fn truncate(output: &[u8], max: usize) -> &[u8] {
+    let end = Boundaries::new(output)
+        .take_while(|&boundary| boundary <= max)
+        .last()
+        .unwrap_or(0);
+    &output[..end]
+}
+
+/// Longest suffix of `output` that is at most `max` bytes long and does not
+/// begin in the middle of a character or of an escape sequence.
+///
+/// This is the counterpart of [`truncate`] for keeping the end of a task's
+/// output.
+///
+/// ```
+/// let output = "\x1b[1mbold\x1b[0m caf\u{e9}".as_bytes();
+///
+/// assert_eq!(oqueue::truncate_start(output, 1), b"");
+/// assert_eq!(oqueue::truncate_start(output, 7), " caf\u{e9}".as_bytes());
+/// assert_eq!(oqueue::truncate_start(output, 10), "\x1b[0m caf\u{e9}".as_bytes());
+/// ```
+pub fn truncate_start(output: &[u8], max: usize) -> &[u8] {
+    let min = output.len().saturating_sub(max);
+    let start = if min == 0 {
+        0
+    } else {
+        Boundaries::new(output)
+            .find(|&boundary| boundary >= min)
+            .unwrap_or(output.len())
+    };
+    &output[start..]
+}
+
+/// Offsets of the places in some output where it can be cut, in increasing
+/// order, starting after the first character or escape sequence and ending
+/// with the length of the output.
+struct Boundaries<'a> {
+    output: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Boundaries<'a> {
+    fn new(output: &'a [u8]) -> Self {
+        Boundaries { output, pos: 0 }
+    }
+}
+
+impl Iterator for Boundaries<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let rest = &self.output[self.pos..];
+        let len = match *rest.first()? {
+            ESC => escape_len(rest),
+            0xc0..=0xdf => char_len(rest, 2),
+            0xe0..=0xef => char_len(rest, 3),
+            0xf0..=0xf7 => char_len(rest, 4),
+            _ => 1,
+        };
+        self.pos += len;
+        Some(self.pos)
+    }
+}
+
+/// Length of the character beginning `rest`, which is `len` if it is
+/// complete, or 1 for a byte beginning an invalid sequence.
+fn char_len(rest: &[u8], len: usize) -> usize {
+    match rest.get(1..len) {
+        Some(tail) if tail.iter().all(|&b| b & 0xc0 == 0x80) => len,
+        _ => 1,
+    }
+}
+
+/// Length of the escape sequence beginning `rest`. One left unfinished at the
+/// end of the output takes up the rest of it.
+pub(crate) fn escape_len(rest: &[u8]) -> usize {
+    match rest.get(1) {
+        // Control sequence, ending with a byte in the range @ to ~.
+        Some(b'[') => match rest[2..].iter().position(|b| (0x40..=0x7e).contains(b)) {
+            Some(end) => 2 + end + 1,
+            None => rest.len(),
+        },
+        // Operating system command, ending with BEL or with ESC \.
+        Some(b']') => {
+            let mut i = 2;
+            while i < rest.len() {
+                match rest[i] {
+                    b'\x07' => return i + 1,
+                    ESC if rest.get(i + 1) == Some(&b'\\') => return i + 2,
+                    _ => i += 1,
+                }
+            }
+            rest.len()
+        }
+        Some(_) => 2,
+        None => 1,
+    }
+}
@@ -0,0 +1,66 @@
+use oqueue::Sequencer;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn test_with_len() {
+    let work = ["a", "b", "c"];
+    let (oqueue, capture) = Sequencer::capture();
+    let oqueue = oqueue.with_len(work.len());
+    let children = AtomicUsize::new(0);
+
+    rayon::scope(|s| {
+        for _ in 0..3 {
+            s.spawn(|_| {
+                while let Some(task) = oqueue.next_task() {
+                    writeln!(task, "{}", work[task.index]);
+                    let child = oqueue.begin_after(&task);
+                    drop(task);
+                    writeln!(child, "child");
+                    children.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    drop(oqueue);
+
+    assert_eq!(children.into_inner(), 3);
+    assert_eq!(capture.string(), "a\nchild\nb\nchild\nc\nchild\n");
+}
+
+#[test]
+fn test_begin_past_end() {
+    let (oqueue, capture) = Sequencer::capture();
+    let oqueue = oqueue.with_len(1);
+
+    let first = oqueue.begin();
+    let past = oqueue.begin();
+    assert_eq!(past.index, 1);
+    writeln!(past, "thrown away");
+    drop(past);
+    assert!(oqueue.next_task().is_none());
+    writeln!(first, "first");
+    drop(first);
+    drop(oqueue);
+
+    assert_eq!(capture.string(), "first\n");
+}
+
+#[test]
+fn test_fail_fast() {
+    let (oqueue, capture) = Sequencer::capture();
+    let oqueue = oqueue.fail_fast(1);
+
+    let first = oqueue.next_task().unwrap();
+    writeln!(first, "first");
+    let child = oqueue.next_task_after(&first).unwrap();
+    first.set_failed();
+    drop(first);
+
+    assert!(oqueue.next_task_after(&child).is_none());
+    assert!(oqueue.next_task().is_none());
+    writeln!(child, "child");
+    drop(child);
+    drop(oqueue);
+
+    assert_eq!(capture.string(), "first\nchild\n");
+}
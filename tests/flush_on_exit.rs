@@ -0,0 +1,34 @@
+#![cfg(feature = "exit")]
+
+use oqueue::Sequencer;
+use std::env;
+use std::process::{self, Command};
+
+const CHILD: &str = "OQUEUE_FLUSH_ON_EXIT_CHILD";
+
+#[test]
+fn test_flush_on_exit() {
+    if env::var_os(CHILD).is_some() {
+        let oqueue = Sequencer::stdout().flush_on_exit();
+        let first = oqueue.begin();
+        let second = oqueue.begin();
+        writeln!(first, "first, unfinished");
+        writeln!(second, "second, finished");
+        drop(second);
+        std::mem::forget(first);
+        process::exit(0);
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "test_flush_on_exit", "--test-threads=1"])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with("first, unfinished\nsecond, finished\n"),
+        "{:?}",
+        stdout,
+    );
+}